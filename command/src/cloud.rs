@@ -0,0 +1,760 @@
+use crate::repos::Repository;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+    process::Command,
+};
+
+/// Context variables with the number of a pull request to check out, and its repository
+const PR_VARIABLES: [(&str, &str); 4] = [
+    ("LILA_PR", "lila"),
+    ("LILA_WS_PR", "lila-ws"),
+    ("SCALACHESS_PR", "scalachess"),
+    ("CHESSGROUND_PR", "chessground"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Visibility {
+    Public,
+    Private,
+    /// Codespaces only: members of the organization that owns the repository
+    Organization,
+}
+
+impl fmt::Display for Visibility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Public => "public",
+            Self::Private => "private",
+            Self::Organization => "org",
+        })
+    }
+}
+
+/// A port forwarded by the environment
+#[derive(Debug, PartialEq)]
+pub struct PortStatus {
+    pub port: u16,
+    /// `None` when nothing listens on the port yet
+    pub visibility: Option<Visibility>,
+}
+
+/// A hosted or containerized development environment that decides how the ports of the
+/// services are reached from the browser
+pub trait CloudEnvironment {
+    fn name(&self) -> &'static str;
+
+    /// The public URL of a port, or `None` when ports are forwarded to this machine
+    /// and the configured hostname applies
+    fn url_for_port(&self, port: u16) -> Option<String>;
+
+    /// Let other clients than the current browser session access the port, or stop them
+    fn set_port_visibility(&self, _port: u16, _visibility: Visibility) -> std::io::Result<()> {
+        Err(self.ports_unsupported())
+    }
+
+    fn port_statuses(&self) -> std::io::Result<Vec<PortStatus>> {
+        Err(self.ports_unsupported())
+    }
+
+    fn ports_unsupported(&self) -> Error {
+        Error::new(
+            ErrorKind::Unsupported,
+            format!(
+                "The visibility of ports cannot be changed in {}",
+                self.name()
+            ),
+        )
+    }
+
+    fn supports_public_ports(&self) -> bool {
+        false
+    }
+
+    /// A variable passed along with the workspace, like the number of a PR to check out
+    fn context_var(&self, name: &str) -> Option<String> {
+        std::env::var(name).ok()
+    }
+
+    fn docs_url(&self) -> &'static str {
+        "https://github.com/lichess-org/lila-docker"
+    }
+}
+
+/// The environment this command runs in, if it is not a plain local machine
+pub fn detect() -> std::io::Result<Option<Box<dyn CloudEnvironment>>> {
+    Ok(if let Some(gitpod) = Gitpod::load()? {
+        Some(Box::new(gitpod))
+    } else if let Some(codespace) = Codespace::load() {
+        Some(Box::new(codespace))
+    } else if Devcontainer::is_host() {
+        Some(Box::new(Devcontainer))
+    } else {
+        None
+    })
+}
+
+/// `https://8080-...` becomes `8080-...`
+pub fn domain_of(url: &str) -> String {
+    url.trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .to_string()
+}
+
+/// Setup choices passed along with the workspace, so that a single link opens a configured
+/// review environment, e.g.
+/// `https://gitpod.io/#LILA_PR=123,SCALACHESS_PR=45,PRESET=backend/https://github.com/lichess-org/lila-docker`
+#[derive(Debug, Default, PartialEq)]
+pub struct WorkspaceContext {
+    pub pull_requests: Vec<(Repository, u32)>,
+    pub preset: Option<String>,
+    /// `SETUP_DATABASE=false` skips seeding the database
+    pub setup_database: Option<bool>,
+    pub setup_api_tokens: Option<bool>,
+}
+
+impl WorkspaceContext {
+    pub fn load(cloud: &dyn CloudEnvironment) -> std::io::Result<Self> {
+        Self::from_vars(|name| cloud.context_var(name))
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> std::io::Result<Self> {
+        let invalid = |name: &str, value: &str| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid {name} in the workspace context: {value}"),
+            )
+        };
+
+        let pull_requests = PR_VARIABLES
+            .iter()
+            .filter_map(|(name, project)| Some((name, project, var(name)?)))
+            .map(|(name, project, value)| {
+                let pr = value
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|_| invalid(name, &value))?;
+                Ok((Repository::new("lichess-org", project), pr))
+            })
+            .collect::<std::io::Result<_>>()?;
+
+        let flag = |name: &str| {
+            var(name)
+                .map(|value| parse_flag(&value).ok_or_else(|| invalid(name, &value)))
+                .transpose()
+        };
+
+        Ok(Self {
+            pull_requests,
+            preset: var("PRESET").filter(|preset| !preset.is_empty()),
+            setup_database: flag("SETUP_DATABASE")?,
+            setup_api_tokens: flag("SETUP_API_TOKENS")?,
+        })
+    }
+}
+
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "yes" | "1" => Some(true),
+        "false" | "no" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+pub struct Gitpod {
+    workspace_url: String,
+    /// The `envvars` of the workspace context, e.g. from `https://gitpod.io/#LILA_PR=123/...`
+    context_vars: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+pub enum GitpodError {
+    /// Gitpod sets it in every workspace, so the environment is only partly inherited
+    MissingVariable(&'static str),
+    InvalidUrl(String),
+    InvalidContext(serde_json::Error),
+}
+
+impl fmt::Display for GitpodError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingVariable(name) => write!(
+                f,
+                "{name} is not set although this looks like a Gitpod workspace. Run the command from a workspace terminal, or unset GITPOD_WORKSPACE_ID to set up as on a local machine"
+            ),
+            Self::InvalidUrl(url) => write!(
+                f,
+                "GITPOD_WORKSPACE_URL is not an https:// URL: {url}"
+            ),
+            Self::InvalidContext(err) => write!(
+                f,
+                "GITPOD_WORKSPACE_CONTEXT is not valid JSON ({err}). Run `unset GITPOD_WORKSPACE_CONTEXT` to set up without the workspace context"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GitpodError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidContext(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// `main` prints the `Debug` form of its error, so keep only the message
+impl From<GitpodError> for Error {
+    fn from(err: GitpodError) -> Self {
+        Error::new(ErrorKind::InvalidData, err.to_string())
+    }
+}
+
+impl Gitpod {
+    fn load() -> Result<Option<Self>, GitpodError> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// `None` outside of Gitpod. A workspace without a context, like a prebuild, has no
+    /// context variables.
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Option<Self>, GitpodError> {
+        let workspace_url = match (var("GITPOD_WORKSPACE_URL"), var("GITPOD_WORKSPACE_ID")) {
+            (None, None) => return Ok(None),
+            (None, Some(_)) => return Err(GitpodError::MissingVariable("GITPOD_WORKSPACE_URL")),
+            (Some(url), _) if !url.starts_with("https://") => {
+                return Err(GitpodError::InvalidUrl(url))
+            }
+            (Some(url), _) => url.trim_end_matches('/').to_string(),
+        };
+
+        let context_vars = match var("GITPOD_WORKSPACE_CONTEXT") {
+            Some(context) if !context.trim().is_empty() => parse_gitpod_context(&context)?,
+            _ => vec![],
+        };
+
+        Ok(Some(Self {
+            workspace_url,
+            context_vars,
+        }))
+    }
+}
+
+/// The `envvars` of the context, a list of `{"name": ..., "value": ...}` or a map, at the
+/// top level or, for workspaces started from a prebuild, in `originalContext`. Anything
+/// else is ignored rather than failing the setup.
+fn parse_gitpod_context(json: &str) -> Result<Vec<(String, String)>, GitpodError> {
+    let context: Value = serde_json::from_str(json).map_err(GitpodError::InvalidContext)?;
+
+    let envvars = context
+        .get("envvars")
+        .filter(|envvars| !envvars.is_null())
+        .or_else(|| context.pointer("/originalContext/envvars"));
+
+    Ok(match envvars {
+        Some(Value::Array(entries)) => entries
+            .iter()
+            .filter_map(|entry| {
+                Some((
+                    entry.get("name")?.as_str()?.to_string(),
+                    scalar_value(entry.get("value")?)?,
+                ))
+            })
+            .collect(),
+        Some(Value::Object(entries)) => entries
+            .iter()
+            .filter_map(|(name, value)| Some((name.clone(), scalar_value(value)?)))
+            .collect(),
+        _ => vec![],
+    })
+}
+
+fn scalar_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+impl CloudEnvironment for Gitpod {
+    fn name(&self) -> &'static str {
+        "Gitpod"
+    }
+
+    fn url_for_port(&self, port: u16) -> Option<String> {
+        Some(
+            self.workspace_url
+                .replace("https://", &format!("https://{port}-")),
+        )
+    }
+
+    fn set_port_visibility(&self, port: u16, visibility: Visibility) -> std::io::Result<()> {
+        run_cli(
+            "gp",
+            &["ports", "visibility", &format!("{port}:{visibility}")],
+        )?;
+        Ok(())
+    }
+
+    fn port_statuses(&self) -> std::io::Result<Vec<PortStatus>> {
+        Ok(parse_gp_ports(&run_cli("gp", &["ports", "list"])?))
+    }
+
+    fn supports_public_ports(&self) -> bool {
+        true
+    }
+
+    fn context_var(&self, name: &str) -> Option<String> {
+        self.context_vars
+            .iter()
+            .find(|(var, _)| var == name)
+            .map(|(_, value)| value.clone())
+    }
+
+    fn docs_url(&self) -> &'static str {
+        "https://lichess-org.github.io/lila-gitpod/"
+    }
+}
+
+/// GitHub Codespaces, which forwards ports to `https://<codespace>-<port>.<domain>`
+pub struct Codespace {
+    name: String,
+    forwarding_domain: String,
+}
+
+impl Codespace {
+    fn load() -> Option<Self> {
+        Some(Self {
+            name: std::env::var("CODESPACE_NAME").ok()?,
+            forwarding_domain: std::env::var("GITHUB_CODESPACES_PORT_FORWARDING_DOMAIN")
+                .unwrap_or("app.github.dev".to_string()),
+        })
+    }
+}
+
+impl CloudEnvironment for Codespace {
+    fn name(&self) -> &'static str {
+        "GitHub Codespaces"
+    }
+
+    fn url_for_port(&self, port: u16) -> Option<String> {
+        Some(format!(
+            "https://{}-{port}.{}",
+            self.name, self.forwarding_domain
+        ))
+    }
+
+    fn set_port_visibility(&self, port: u16, visibility: Visibility) -> std::io::Result<()> {
+        run_cli(
+            "gh",
+            &[
+                "codespace",
+                "ports",
+                "visibility",
+                &format!("{port}:{visibility}"),
+                "--codespace",
+                &self.name,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn port_statuses(&self) -> std::io::Result<Vec<PortStatus>> {
+        parse_gh_ports(&run_cli(
+            "gh",
+            &[
+                "codespace",
+                "ports",
+                "--json",
+                "sourcePort,visibility",
+                "--codespace",
+                &self.name,
+            ],
+        )?)
+    }
+
+    fn supports_public_ports(&self) -> bool {
+        true
+    }
+}
+
+/// Run the CLI of the environment, failing with its error output
+fn run_cli(program: &str, args: &[&str]) -> std::io::Result<String> {
+    let output = Command::new(program).args(args).output().map_err(|err| {
+        if err.kind() == ErrorKind::NotFound {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("`{program}` is not installed in this workspace"),
+            )
+        } else {
+            err
+        }
+    })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        return Err(Error::other(format!(
+            "`{program} {}` failed: {}",
+            args.join(" "),
+            if stderr.trim().is_empty() {
+                stdout.trim()
+            } else {
+                stderr.trim()
+            }
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The table of `gp ports list`, one port per row:
+/// `  8080  |  open (public)  |  https://8080-...  |  lichess: Lichess dev site`
+fn parse_gp_ports(output: &str) -> Vec<PortStatus> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut cells = line
+                .split('|')
+                .map(str::trim)
+                .filter(|cell| !cell.is_empty());
+            let port = cells.next()?.parse().ok()?;
+            let status = cells.next().unwrap_or_default();
+
+            Some(PortStatus {
+                port,
+                visibility: if status.contains("(public)") {
+                    Some(Visibility::Public)
+                } else if status.contains("(private)") {
+                    Some(Visibility::Private)
+                } else {
+                    None
+                },
+            })
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GhPort {
+    source_port: u16,
+    visibility: String,
+}
+
+/// `gh codespace ports --json sourcePort,visibility`
+fn parse_gh_ports(output: &str) -> std::io::Result<Vec<PortStatus>> {
+    let ports: Vec<GhPort> = serde_json::from_str(output)?;
+
+    Ok(ports
+        .into_iter()
+        .map(|port| PortStatus {
+            port: port.source_port,
+            visibility: match port.visibility.as_str() {
+                "public" => Some(Visibility::Public),
+                "private" => Some(Visibility::Private),
+                "org" => Some(Visibility::Organization),
+                _ => None,
+            },
+        })
+        .collect())
+}
+
+/// The dev container of `.devcontainer/devcontainer.json`, whose ports the editor
+/// forwards to localhost
+pub struct Devcontainer;
+
+impl Devcontainer {
+    fn is_host() -> bool {
+        std::env::var("LILA_DOCKER_DEVCONTAINER").is_ok()
+    }
+}
+
+impl CloudEnvironment for Devcontainer {
+    fn name(&self) -> &'static str {
+        "the dev container"
+    }
+
+    fn url_for_port(&self, _port: u16) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gitpod_lila_url() {
+        std::env::set_var(
+            "GITPOD_WORKSPACE_URL",
+            "https://lichessorg-liladocker-abc123.ws-us123.gitpod.io",
+        );
+        std::env::set_var("GITPOD_WORKSPACE_CONTEXT", "{}");
+
+        let gitpod = Gitpod::load().unwrap().unwrap();
+        let url = gitpod.url_for_port(8080).unwrap();
+        assert_eq!(
+            url,
+            "https://8080-lichessorg-liladocker-abc123.ws-us123.gitpod.io"
+        );
+        assert_eq!(
+            domain_of(&url),
+            "8080-lichessorg-liladocker-abc123.ws-us123.gitpod.io"
+        );
+        assert_eq!(
+            gitpod.url_for_port(8025).unwrap(),
+            "https://8025-lichessorg-liladocker-abc123.ws-us123.gitpod.io"
+        );
+        assert!(gitpod.context_vars.is_empty());
+        assert_eq!(gitpod.context_var("LILA_PR"), None);
+    }
+
+    #[test]
+    fn test_gitpod_lila_url_with_pr_context() {
+        std::env::set_var(
+            "GITPOD_WORKSPACE_URL",
+            "https://lichessorg-liladocker-abc123.ws-us123.gitpod.io",
+        );
+        std::env::set_var(
+            "GITPOD_WORKSPACE_CONTEXT",
+            r#"{"envvars":[{"name":"LILA_PR","value":"12345"}]}"#,
+        );
+
+        let gitpod = Gitpod::load().unwrap().unwrap();
+
+        assert_eq!(
+            gitpod.context_vars,
+            vec![("LILA_PR".to_string(), "12345".to_string())]
+        );
+
+        assert_eq!(gitpod.context_var("LILA_PR").as_deref(), Some("12345"));
+    }
+
+    #[test]
+    fn test_gitpod_lila_url_with_context_but_no_pr() {
+        std::env::set_var(
+            "GITPOD_WORKSPACE_URL",
+            "https://lichessorg-liladocker-abc123.ws-us123.gitpod.io",
+        );
+        std::env::set_var(
+            "GITPOD_WORKSPACE_CONTEXT",
+            r#"{"envvars":[{"name":"FOO","value":"BAR"}]}"#,
+        );
+
+        let gitpod = Gitpod::load().unwrap().unwrap();
+
+        assert_eq!(
+            gitpod.context_vars,
+            vec![("FOO".to_string(), "BAR".to_string())]
+        );
+
+        assert_eq!(gitpod.context_var("FOO").as_deref(), Some("BAR"));
+        assert_eq!(gitpod.context_var("LILA_PR"), None);
+    }
+
+    #[test]
+    fn test_gitpod_missing_variables() {
+        assert!(Gitpod::from_vars(|_| None).unwrap().is_none());
+
+        let err = Gitpod::from_vars(|name| {
+            (name == "GITPOD_WORKSPACE_ID").then(|| "lichessorg-liladocker-abc123".to_string())
+        })
+        .err()
+        .unwrap();
+        assert!(matches!(
+            err,
+            GitpodError::MissingVariable("GITPOD_WORKSPACE_URL")
+        ));
+
+        let gitpod = Gitpod::from_vars(|name| {
+            (name == "GITPOD_WORKSPACE_URL")
+                .then(|| "https://lichessorg-liladocker-abc123.ws-us123.gitpod.io/".to_string())
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            gitpod.url_for_port(8080).unwrap(),
+            "https://8080-lichessorg-liladocker-abc123.ws-us123.gitpod.io"
+        );
+        assert!(gitpod.context_vars.is_empty());
+
+        assert!(matches!(
+            Gitpod::from_vars(|name| (name == "GITPOD_WORKSPACE_URL")
+                .then(|| "lichessorg-liladocker-abc123.gitpod.io".to_string()))
+            .err()
+            .unwrap(),
+            GitpodError::InvalidUrl(_)
+        ));
+    }
+
+    #[test]
+    fn test_gitpod_invalid_context() {
+        let err = parse_gitpod_context(r#"{"envvars":[{"name":"LILA_PR""#).unwrap_err();
+        assert!(matches!(err, GitpodError::InvalidContext(_)));
+        assert!(Error::from(err)
+            .to_string()
+            .starts_with("GITPOD_WORKSPACE_CONTEXT is not valid JSON"));
+    }
+
+    #[test]
+    fn test_gitpod_context_shapes() {
+        let expected = vec![
+            ("LILA_PR".to_string(), "12345".to_string()),
+            ("SETUP_DATABASE".to_string(), "false".to_string()),
+        ];
+
+        // Numbers and booleans, and entries without a name are skipped
+        assert_eq!(
+            parse_gitpod_context(
+                r#"{"envvars":[{"name":"LILA_PR","value":12345},{"value":"x"},{"name":"SETUP_DATABASE","value":false}]}"#
+            )
+            .unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_gitpod_context(r#"{"envvars":{"LILA_PR":"12345","SETUP_DATABASE":"false"}}"#)
+                .unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_gitpod_context(
+                r#"{"snapshotBucketId":"abc","originalContext":{"ref":"main","envvars":[{"name":"LILA_PR","value":"12345"},{"name":"SETUP_DATABASE","value":"false"}]}}"#
+            )
+            .unwrap(),
+            expected
+        );
+
+        for context in [
+            "{}",
+            r#"{"envvars":null}"#,
+            r#"{"envvars":"LILA_PR=12345"}"#,
+            r#""https://github.com/lichess-org/lila-docker""#,
+            "[]",
+        ] {
+            assert!(
+                parse_gitpod_context(context).unwrap().is_empty(),
+                "{context}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_workspace_context() {
+        let vars = |name: &str| match name {
+            "LILA_PR" => Some("12345".to_string()),
+            "SCALACHESS_PR" => Some("678".to_string()),
+            "PRESET" => Some("backend".to_string()),
+            "SETUP_DATABASE" => Some("false".to_string()),
+            _ => None,
+        };
+
+        assert_eq!(
+            WorkspaceContext::from_vars(vars).unwrap(),
+            WorkspaceContext {
+                pull_requests: vec![
+                    (Repository::new("lichess-org", "lila"), 12345),
+                    (Repository::new("lichess-org", "scalachess"), 678),
+                ],
+                preset: Some("backend".to_string()),
+                setup_database: Some(false),
+                setup_api_tokens: None,
+            }
+        );
+        assert_eq!(
+            WorkspaceContext::from_vars(|_| None).unwrap(),
+            WorkspaceContext::default()
+        );
+    }
+
+    #[test]
+    fn test_workspace_context_invalid_values() {
+        let err = WorkspaceContext::from_vars(|name| {
+            (name == "CHESSGROUND_PR").then(|| "latest".to_string())
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid CHESSGROUND_PR in the workspace context: latest"
+        );
+        assert!(WorkspaceContext::from_vars(|name| {
+            (name == "SETUP_API_TOKENS").then(|| "maybe".to_string())
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_gp_ports() {
+        let output = "  PORT  |     STATUS     |                      URL                      |  NAME & DESCRIPTION
+--------+----------------+-----------------------------------------------+----------------------------
+  8025  | open (private) | https://8025-lichessorg-liladocker-abc123.gitpod.io | mailpit: Email inbox
+  8080  | open (public)  | https://8080-lichessorg-liladocker-abc123.gitpod.io | lichess: Lichess dev site
+  8081  | not served     | https://8081-lichessorg-liladocker-abc123.gitpod.io | mongo-express: Database explorer
+";
+        assert_eq!(
+            parse_gp_ports(output),
+            vec![
+                PortStatus {
+                    port: 8025,
+                    visibility: Some(Visibility::Private)
+                },
+                PortStatus {
+                    port: 8080,
+                    visibility: Some(Visibility::Public)
+                },
+                PortStatus {
+                    port: 8081,
+                    visibility: None
+                },
+            ]
+        );
+
+        // Older versions draw a border around the table
+        assert_eq!(
+            parse_gp_ports("| 8080 | open (public) | https://8080-x.gitpod.io | lichess |\n"),
+            vec![PortStatus {
+                port: 8080,
+                visibility: Some(Visibility::Public)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_gh_ports() {
+        assert_eq!(
+            parse_gh_ports(
+                r#"[{"sourcePort":8080,"visibility":"public"},{"sourcePort":8025,"visibility":"org"}]"#
+            )
+            .unwrap(),
+            vec![
+                PortStatus {
+                    port: 8080,
+                    visibility: Some(Visibility::Public)
+                },
+                PortStatus {
+                    port: 8025,
+                    visibility: Some(Visibility::Organization)
+                },
+            ]
+        );
+        assert!(parse_gh_ports("not json").is_err());
+    }
+
+    #[test]
+    fn test_codespace_urls() {
+        let codespace = Codespace {
+            name: "octocat-lila-docker-x7g9".to_string(),
+            forwarding_domain: "app.github.dev".to_string(),
+        };
+        let url = codespace.url_for_port(8080).unwrap();
+        assert_eq!(url, "https://octocat-lila-docker-x7g9-8080.app.github.dev");
+        assert_eq!(
+            domain_of(&url),
+            "octocat-lila-docker-x7g9-8080.app.github.dev"
+        );
+        assert_eq!(Devcontainer.url_for_port(8080), None);
+    }
+}
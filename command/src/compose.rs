@@ -0,0 +1,142 @@
+use crate::Config;
+use cliclack::{confirm, log::info, spinner};
+use std::{io::Error, path::Path, process::Command};
+
+/// Run `docker compose` with the profiles and compose files of the given configuration,
+/// which may not be exported to the environment yet
+pub fn docker_compose(config: &Config, args: &[&str]) -> std::io::Result<String> {
+    let profiles = config.compose_profiles.clone().unwrap_or_default();
+    let output = Command::new("docker")
+        .arg("compose")
+        .args(
+            profiles
+                .iter()
+                .flat_map(|profile| ["--profile", profile.as_str()]),
+        )
+        .args(args)
+        .env("COMPOSE_FILE", config.compose_file_paths().join(":"))
+        .output()?;
+
+    if !output.status.success() {
+        return Err(Error::other(format!(
+            "`docker compose {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Compose services with a volume mounted from inside the given directory,
+/// read from the output of `docker compose config --format json`
+fn services_mounting(compose_config: &str, dir: &Path) -> std::io::Result<Vec<String>> {
+    let config: serde_json::Value = serde_json::from_str(compose_config)?;
+
+    let Some(services) = config["services"].as_object() else {
+        return Ok(vec![]);
+    };
+
+    Ok(services
+        .iter()
+        .filter(|(_, service)| {
+            service["volumes"].as_array().is_some_and(|volumes| {
+                volumes.iter().any(|volume| {
+                    volume["source"]
+                        .as_str()
+                        .is_some_and(|source| Path::new(source).starts_with(dir))
+                })
+            })
+        })
+        .map(|(name, _)| name.clone())
+        .collect())
+}
+
+/// Running containers that mount the directory, relative to the lila-docker root
+fn running_services_mounting(config: &Config, dir: &Path) -> std::io::Result<Vec<String>> {
+    let dir = std::env::current_dir()?.join(dir);
+    let mounting = services_mounting(
+        &docker_compose(config, &["config", "--format", "json"])?,
+        &dir,
+    )?;
+    let running = docker_compose(config, &["ps", "--services", "--status", "running"])?;
+
+    Ok(mounting
+        .into_iter()
+        .filter(|service| running.lines().any(|line| line == service))
+        .collect())
+}
+
+/// Offer to recreate the running containers that mount the directory, so they pick up
+/// new code or new volumes
+pub fn offer_restart(config: &Config, dir: &Path) -> std::io::Result<()> {
+    let services = match running_services_mounting(config, dir) {
+        Ok(services) => services,
+        Err(err) => {
+            return info(format!(
+                "Could not determine the containers using {}: {err}",
+                dir.display()
+            ))
+        }
+    };
+
+    if services.is_empty() {
+        return Ok(());
+    }
+
+    if confirm(format!(
+        "Restart {} to use the new code?",
+        services.join(", ")
+    ))
+    .interact()?
+    {
+        let progress = spinner();
+        progress.start("Restarting containers...");
+        let mut args = vec!["up", "--detach", "--no-deps", "--force-recreate"];
+        args.extend(services.iter().map(String::as_str));
+        match docker_compose(config, &args) {
+            Ok(_) => progress.stop(format!("✓ Restarted {}", services.join(", "))),
+            Err(err) => progress.error(format!("✗ Failed to restart: {err}")),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_services_mounting() {
+        let compose_config = r#"{
+            "services": {
+                "lila": {"volumes": [
+                    {"type": "bind", "source": "/home/me/lila-docker/repos/lila", "target": "/lila"},
+                    {"type": "bind", "source": "/home/me/lila-docker/repos/scalachess", "target": "/scalachess"}
+                ]},
+                "nginx": {"volumes": [
+                    {"type": "bind", "source": "/home/me/lila-docker/repos/lila/public", "target": "/lila/public"}
+                ]},
+                "lila_ws": {"volumes": [
+                    {"type": "bind", "source": "/home/me/lila-docker/repos/lila-ws", "target": "/lila-ws"}
+                ]},
+                "redis": {}
+            }
+        }"#;
+
+        assert_eq!(
+            services_mounting(compose_config, Path::new("/home/me/lila-docker/repos/lila"))
+                .unwrap(),
+            vec!["lila", "nginx"]
+        );
+        assert_eq!(
+            services_mounting(
+                compose_config,
+                Path::new("/home/me/lila-docker/repos/scalachess")
+            )
+            .unwrap(),
+            vec!["lila"]
+        );
+    }
+}
@@ -0,0 +1,471 @@
+use crate::{cloud, Config};
+use cliclack::{
+    confirm, input, intro,
+    log::{error, info, step, warning},
+    outro, select,
+};
+use local_ip_address::list_afinet_netifas;
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+    net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs},
+    process::Command,
+    thread,
+    time::Duration,
+};
+
+/// How many custom hostnames `settings.toml` remembers
+const RECENT_HOSTNAMES: usize = 5;
+
+/// The port nginx publishes the site on
+const NGINX_PORT: u16 = 8080;
+
+/// The address of the host as seen from an Android emulator, not bound on the host itself
+const EMULATOR_HOST: &str = "10.0.2.2";
+
+/// nginx takes a moment to listen again after `./lila-docker hostname` restarts it
+const PROBE_ATTEMPTS: u32 = 10;
+
+/// An entry of the hostname picker
+#[derive(Debug, PartialEq)]
+struct Choice {
+    hostname: String,
+    hint: String,
+}
+
+impl Choice {
+    fn new(hostname: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            hostname: hostname.into(),
+            hint: hint.into(),
+        }
+    }
+}
+
+/// The address as it appears in a URL, with IPv6 in brackets
+fn url_host(ip: &IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("[{ip}]"),
+    }
+}
+
+/// Link-local IPv6 addresses only work with a zone id (`fe80::1%eth0`), which browsers reject
+fn is_reachable(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !ip.is_loopback(),
+        IpAddr::V6(ip) => !ip.is_loopback() && (ip.segments()[0] & 0xffc0) != 0xfe80,
+    }
+}
+
+fn describe_interface(name: &str) -> String {
+    let kind = if name == "docker0" || name.starts_with("br-") {
+        "Docker bridge, for containers on this machine"
+    } else if name.starts_with("tailscale") || name.starts_with("utun") {
+        "Tailscale or VPN, for devices on the same tailnet or VPN"
+    } else if name.starts_with("tun") || name.starts_with("wg") {
+        "VPN, for devices on the same VPN"
+    } else {
+        "for other devices on the same network"
+    };
+    format!("{name}: {kind}")
+}
+
+/// localhost, the addresses of the network interfaces, the hostname of the machine,
+/// the emulator alias and the recently entered hostnames, without duplicates
+fn choices(
+    interfaces: &[(String, IpAddr)],
+    machine_names: &[String],
+    recent: &[String],
+) -> Vec<Choice> {
+    let mut choices = vec![Choice::new("localhost", "default")];
+
+    choices.extend(
+        interfaces
+            .iter()
+            .filter(|(_, ip)| is_reachable(ip))
+            .map(|(name, ip)| Choice::new(url_host(ip), describe_interface(name))),
+    );
+    choices.extend(machine_names.iter().map(|name| {
+        Choice::new(
+            name,
+            "The name of this machine, if your network resolves it",
+        )
+    }));
+    choices.push(Choice::new(
+        EMULATOR_HOST,
+        "For accessing from an Android emulator running on this machine",
+    ));
+    choices.extend(
+        recent
+            .iter()
+            .map(|hostname| Choice::new(hostname, "Recently entered")),
+    );
+
+    let mut seen = vec![];
+    choices.retain(|choice| {
+        let new = !seen.contains(&choice.hostname);
+        seen.push(choice.hostname.clone());
+        new
+    });
+    choices
+}
+
+/// `hostname` and `hostname -f`, which is the same on machines without a domain
+fn machine_names() -> Vec<String> {
+    let mut names: Vec<String> = [&[][..], &["-f"]]
+        .iter()
+        .filter_map(|args| Command::new("hostname").args(*args).output().ok())
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|name| !name.is_empty() && name != "localhost")
+        .collect();
+    names.dedup();
+    names
+}
+
+/// Move the hostname to the front of the recent ones, dropping the oldest
+fn remember(recent: &[String], hostname: &str) -> Vec<String> {
+    std::iter::once(hostname.to_string())
+        .chain(recent.iter().filter(|recent| *recent != hostname).cloned())
+        .take(RECENT_HOSTNAMES)
+        .collect()
+}
+
+/// The hostname of a `lila_domain`, e.g. `192.168.1.2` of `192.168.1.2:8080`
+pub fn of_domain(domain: &str) -> &str {
+    domain.rsplit_once(':').map_or(domain, |(host, _)| host)
+}
+
+fn configured_hostname(config: &Config) -> Option<&str> {
+    config.lila_domain.as_deref().map(of_domain)
+}
+
+/// The checks from a hostname to the site, in order
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Check {
+    Resolve,
+    LocalAddress,
+    Connect,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Resolve => "name resolution",
+            Self::LocalAddress => "local address",
+            Self::Connect => "connection to nginx",
+        })
+    }
+}
+
+fn check_failed(check: Check, hostname: &str) -> Error {
+    Error::other(format!("The {check} check failed for {hostname}"))
+}
+
+fn resolve(hostname: &str) -> std::io::Result<Vec<IpAddr>> {
+    let host = hostname.trim_start_matches('[').trim_end_matches(']');
+    let mut ips: Vec<IpAddr> = (host, NGINX_PORT)
+        .to_socket_addrs()?
+        .map(|address| address.ip())
+        .collect();
+    ips.dedup();
+
+    if ips.is_empty() {
+        return Err(Error::new(ErrorKind::NotFound, "no address found"));
+    }
+    Ok(ips)
+}
+
+/// Whether one of the addresses reaches this machine, so that nginx answers on it
+fn is_local(resolved: &[IpAddr], local: &[IpAddr]) -> bool {
+    resolved
+        .iter()
+        .any(|ip| ip.is_loopback() || ip.is_unspecified() || local.contains(ip))
+}
+
+fn local_addresses() -> Vec<IpAddr> {
+    list_afinet_netifas()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(_, ip)| ip)
+        .collect()
+}
+
+fn format_ips(ips: &[IpAddr]) -> String {
+    ips.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Resolve the hostname and warn when it points to another machine. Returns the addresses.
+fn check_resolution(hostname: &str) -> std::io::Result<Vec<IpAddr>> {
+    let ips = match resolve(hostname) {
+        Ok(ips) => ips,
+        Err(err) => {
+            error(format!(
+                "✗ {}: {hostname} does not resolve ({err})",
+                Check::Resolve
+            ))?;
+            return Err(check_failed(Check::Resolve, hostname));
+        }
+    };
+    step(format!(
+        "✓ {}: {hostname} resolves to {}",
+        Check::Resolve,
+        format_ips(&ips)
+    ))?;
+
+    if hostname == EMULATOR_HOST {
+        info(format!(
+            "{EMULATOR_HOST} only reaches this machine from an Android emulator"
+        ))?;
+    } else if is_local(&ips, &local_addresses()) {
+        step(format!(
+            "✓ {}: it is bound on this machine",
+            Check::LocalAddress
+        ))?;
+    } else {
+        warning(format!(
+            "! {}: {} is not an address of this machine, {hostname} may reach another one",
+            Check::LocalAddress,
+            format_ips(&ips)
+        ))?;
+    }
+
+    Ok(ips)
+}
+
+/// Connect to the port of nginx on one of the addresses, retrying while it restarts
+fn probe(ips: &[IpAddr]) -> std::io::Result<SocketAddr> {
+    let mut last_error = Error::new(ErrorKind::NotFound, "no address to connect to");
+
+    for attempt in 1..=PROBE_ATTEMPTS {
+        for ip in ips {
+            let address = SocketAddr::new(*ip, NGINX_PORT);
+            match TcpStream::connect_timeout(&address, Duration::from_secs(2)) {
+                Ok(_) => return Ok(address),
+                Err(err) => last_error = err,
+            }
+        }
+        if attempt < PROBE_ATTEMPTS {
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+
+    Err(last_error)
+}
+
+/// `hostname verify`, run by `./lila-docker hostname` after restarting nginx
+fn verify(config: &Config) -> std::io::Result<()> {
+    let hostname = configured_hostname(config).unwrap_or("localhost");
+    intro(format!(
+        "Checking that http://{hostname}:{NGINX_PORT} is reachable..."
+    ))?;
+
+    let ips = check_resolution(hostname)?;
+
+    if hostname == EMULATOR_HOST {
+        return outro("Open the site from the emulator to check the connection");
+    }
+
+    match probe(&ips) {
+        Ok(address) => {
+            step(format!(
+                "✓ {}: {address} accepts connections",
+                Check::Connect
+            ))?;
+            outro(format!("✔ http://{hostname}:{NGINX_PORT} is reachable"))
+        }
+        Err(err) => {
+            error(format!(
+                "✗ {}: nothing answers on port {NGINX_PORT} of {} ({err}). Check that nginx is running with `docker compose ps nginx` and that no firewall blocks the port",
+                Check::Connect,
+                format_ips(&ips)
+            ))?;
+            Err(check_failed(Check::Connect, hostname))
+        }
+    }
+}
+
+/// `hostname` to pick one, `hostname verify` to check the configured one
+pub fn run(args: &[String], mut config: Config) -> std::io::Result<()> {
+    match args {
+        [] => {}
+        [verify_arg] if verify_arg == "verify" => return verify(&config),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Usage: hostname [verify]",
+            ))
+        }
+    }
+
+    if let Some(cloud) = cloud::detect()?.filter(|cloud| cloud.url_for_port(8080).is_some()) {
+        return error(format!(
+            "Setting of hostname not available on {}",
+            cloud.name()
+        ));
+    }
+
+    let recent = config.recent_hostnames.clone().unwrap_or_default();
+    let choices = choices(
+        &list_afinet_netifas().unwrap_or_default(),
+        &machine_names(),
+        &recent,
+    );
+
+    let current = configured_hostname(&config)
+        .filter(|current| choices.iter().any(|choice| choice.hostname == *current))
+        .unwrap_or("localhost");
+
+    let mut prompt = select("Select a hostname to access your local Lichess instance:")
+        .initial_value(Some(current.to_string()));
+    for choice in &choices {
+        prompt = prompt.item(
+            Some(choice.hostname.clone()),
+            &choice.hostname,
+            &choice.hint,
+        );
+    }
+
+    let (hostname, custom) = match prompt
+        .item(None, "Other", "Enter a custom hostname")
+        .interact()?
+    {
+        Some(hostname) => {
+            let custom = recent.contains(&hostname);
+            (hostname, custom)
+        }
+        None => (
+            input("Enter a custom hostname:  (It must be resolvable)").interact()?,
+            true,
+        ),
+    };
+
+    if let Err(err) = check_resolution(&hostname) {
+        if !confirm(format!("Use {hostname} anyway?"))
+            .initial_value(false)
+            .interact()?
+        {
+            return Err(err);
+        }
+    }
+
+    if custom {
+        config.recent_hostnames = Some(remember(&recent, &hostname));
+    }
+    config.lila_domain = Some(format!("{hostname}:8080"));
+    config.lila_url = Some(format!("http://{hostname}:8080"));
+    config.save()?;
+
+    outro(format!("✔ Local Lichess URL set to http://{hostname}:8080"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hostnames(choices: &[Choice]) -> Vec<&str> {
+        choices
+            .iter()
+            .map(|choice| choice.hostname.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_choices() {
+        let interfaces = vec![
+            ("lo".to_string(), "127.0.0.1".parse().unwrap()),
+            ("eth0".to_string(), "192.168.1.2".parse().unwrap()),
+            ("docker0".to_string(), "172.17.0.1".parse().unwrap()),
+            ("tailscale0".to_string(), "100.101.102.103".parse().unwrap()),
+            ("lo".to_string(), "::1".parse().unwrap()),
+            (
+                "eth0".to_string(),
+                "fe80::1c2b:3aff:fe4d:5e6f".parse().unwrap(),
+            ),
+            ("eth0".to_string(), "fd00::1234".parse().unwrap()),
+        ];
+        let machine_names = vec!["devbox".to_string(), "devbox.example.org".to_string()];
+        let recent = vec!["lichess.local".to_string(), "192.168.1.2".to_string()];
+
+        let choices = choices(&interfaces, &machine_names, &recent);
+
+        assert_eq!(
+            hostnames(&choices),
+            vec![
+                "localhost",
+                "192.168.1.2",
+                "172.17.0.1",
+                "100.101.102.103",
+                "[fd00::1234]",
+                "devbox",
+                "devbox.example.org",
+                "10.0.2.2",
+                "lichess.local",
+            ]
+        );
+        assert_eq!(
+            choices[2].hint,
+            "docker0: Docker bridge, for containers on this machine"
+        );
+        assert_eq!(choices[8].hint, "Recently entered");
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(
+            resolve("127.0.0.1").unwrap(),
+            vec!["127.0.0.1".parse::<IpAddr>().unwrap()]
+        );
+        assert_eq!(
+            resolve("[fd00::1234]").unwrap(),
+            vec!["fd00::1234".parse::<IpAddr>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_is_local() {
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+        let local = vec![ip("192.168.1.2"), ip("fd00::1234")];
+
+        assert!(is_local(&[ip("127.0.0.1")], &local));
+        assert!(is_local(&[ip("::1")], &local));
+        assert!(is_local(&[ip("10.8.0.1"), ip("192.168.1.2")], &local));
+        assert!(is_local(&[ip("fd00::1234")], &local));
+        assert!(!is_local(&[ip("192.168.1.3")], &local));
+        assert!(!is_local(&[], &local));
+    }
+
+    #[test]
+    fn test_remember() {
+        let recent: Vec<String> = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(remember(&recent, "f"), vec!["f", "a", "b", "c", "d"]);
+        assert_eq!(remember(&recent, "c"), vec!["c", "a", "b", "d", "e"]);
+        assert_eq!(remember(&[], "lichess.local"), vec!["lichess.local"]);
+    }
+
+    #[test]
+    fn test_configured_hostname() {
+        let config = |lila_domain: &str| Config {
+            lila_domain: Some(lila_domain.to_string()),
+            ..Config::default()
+        };
+
+        assert_eq!(
+            configured_hostname(&config("192.168.1.2:8080")),
+            Some("192.168.1.2")
+        );
+        assert_eq!(
+            configured_hostname(&config("[fd00::1234]:8080")),
+            Some("[fd00::1234]")
+        );
+        assert_eq!(configured_hostname(&Config::default()), None);
+    }
+}
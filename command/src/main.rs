@@ -5,15 +5,25 @@ use cliclack::{
     log::{error, info, step, warning},
     multiselect, note, outro, select, spinner,
 };
-use local_ip_address::local_ip;
+use cloud::{CloudEnvironment, Visibility, WorkspaceContext};
+use repos::{Lockfile, Repository};
+use resources::{HostCapacity, Resources};
 use serde::{Deserialize, Serialize};
+use services::{OptionalService, Preset, ServiceUrl};
 use std::{
+    collections::BTreeMap,
     format,
     io::{Error, ErrorKind},
-    path::{Path, PathBuf},
-    process::Command,
+    path::PathBuf,
 };
 
+mod cloud;
+mod compose;
+mod hostname;
+mod repos;
+mod resources;
+mod services;
+
 const BANNER: &str = r"
    |\_    _ _      _
    /o \  | (_) ___| |__   ___  ___ ___   ___  _ __ __ _
@@ -28,6 +38,7 @@ const DEFAULT_PASSWORD: &str = "password";
 #[derive(Serialize, Deserialize, Debug, Default)]
 struct Config {
     compose_profiles: Option<Vec<String>>,
+    compose_files: Option<Vec<String>>,
     setup_database: Option<bool>,
     setup_bbppairings: Option<bool>,
     enable_monitoring: Option<bool>,
@@ -37,10 +48,21 @@ struct Config {
     setup_api_tokens: Option<bool>,
     lila_domain: Option<String>,
     lila_url: Option<String>,
+    /// Entered in `./lila-docker hostname`, most recent first
+    recent_hostnames: Option<Vec<String>>,
     phone_ip: Option<String>,
     connection_port: Option<u16>,
     pairing_code: Option<u32>,
     pairing_port: Option<u16>,
+    github_username: Option<String>,
+    clone_depth: Option<u32>,
+    mirror: Option<String>,
+    mirror_reference: Option<bool>,
+    git_hooks: Option<bool>,
+    forks: Option<BTreeMap<String, String>>,
+    clone_depths: Option<BTreeMap<String, u32>>,
+    refs: Option<BTreeMap<String, String>>,
+    worktrees: Option<BTreeMap<String, String>>,
 }
 
 macro_rules! to_env {
@@ -58,6 +80,7 @@ macro_rules! to_env {
 impl Config {
     const SETTINGS_TOML: &'static str = "settings.toml";
     const SETTINGS_ENV: &'static str = "settings.env";
+    const DOCKER_COMPOSE_YML: &'static str = "docker-compose.yml";
 
     fn load() -> Self {
         std::fs::read_to_string(Self::SETTINGS_TOML).map_or_else(
@@ -75,9 +98,17 @@ impl Config {
         toml::to_string(&self).unwrap()
     }
 
+    /// The main compose file followed by the fragments of extra services and overrides
+    fn compose_file_paths(&self) -> Vec<String> {
+        std::iter::once(Self::DOCKER_COMPOSE_YML.to_string())
+            .chain(self.compose_files.clone().unwrap_or_default())
+            .collect()
+    }
+
     fn to_env(&self) -> String {
         let Self {
             compose_profiles,
+            compose_files,
             setup_database,
             setup_bbppairings,
             enable_monitoring,
@@ -87,18 +118,33 @@ impl Config {
             setup_api_tokens,
             lila_domain,
             lila_url,
+            recent_hostnames: _,
             phone_ip,
             connection_port,
             pairing_code,
             pairing_port,
+            github_username: _,
+            clone_depth: _,
+            mirror: _,
+            mirror_reference: _,
+            git_hooks: _,
+            forks: _,
+            clone_depths: _,
+            refs: _,
+            worktrees: _,
         } = self;
         let compose_profiles_string = compose_profiles
             .clone()
             .map(|v| v.join(","))
             .unwrap_or_default();
-
+        let compose_file_string = compose_files
+            .as_ref()
+            .map(|_| self.compose_file_paths().join(":"));
         vec![
             to_env!(compose_profiles, compose_profiles_string),
+            compose_file_string
+                .map(|value| to_env!(compose_file, value))
+                .unwrap_or_default(),
             to_env!(setup_database),
             to_env!(setup_bbppairings),
             to_env!(enable_monitoring),
@@ -116,96 +162,30 @@ impl Config {
         .iter()
         .filter(|line| !line.is_empty())
         .map(std::string::ToString::to_string)
+        .chain(self.lila_config_overrides())
         .collect::<Vec<String>>()
         .join("\n")
     }
-}
-
-#[derive(Clone, Eq, PartialEq, Debug)]
-struct Repository {
-    org: String,
-    project: String,
-}
-
-impl Repository {
-    fn new(org: &str, project: &str) -> Self {
-        Self {
-            org: org.to_string(),
-            project: project.to_string(),
-        }
-    }
 
-    fn full_name(&self) -> String {
-        format!("{}/{}", self.org, self.project)
+    /// lila settings for the enabled services that `conf/lila.conf` picks up from the environment
+    fn lila_config_overrides(&self) -> Vec<String> {
+        let profiles = self.compose_profiles.clone().unwrap_or_default();
+        let lila_url = self.lila_url.as_deref().unwrap_or("http://localhost:8080");
+
+        OptionalService::builtin()
+            .into_iter()
+            .filter(|service| profiles.iter().any(|profile| service.has_profile(profile)))
+            .flat_map(|service| service.lila_config)
+            .map(|(name, value)| format!("{name}={}", value.replace("{lila_url}", lila_url)))
+            .collect()
     }
-
-    fn url(&self) -> String {
-        format!("https://github.com/{}/{}", self.org, self.project)
-    }
-
-    fn clone_path(&self) -> PathBuf {
-        Path::new("repos").join(&self.project)
-    }
-}
-
-struct Gitpod {
-    domain: String,
-    url: String,
-    workspace_context: GitpodWorkspaceContext,
-}
-
-#[derive(Debug, Default, Deserialize, PartialEq)]
-struct GitpodWorkspaceContext {
-    envvars: Option<Vec<GitpodEnvVar>>,
-}
-
-#[derive(Debug, Deserialize, PartialEq)]
-struct GitpodEnvVar {
-    name: String,
-    value: String,
-}
-
-impl Gitpod {
-    fn load() -> Self {
-        let workspace_url =
-            std::env::var("GITPOD_WORKSPACE_URL").expect("Missing env GITPOD_WORKSPACE_URL");
-
-        let workspace_context: GitpodWorkspaceContext = serde_json::from_str(
-            &std::env::var("GITPOD_WORKSPACE_CONTEXT")
-                .expect("Missing env GITPOD_WORKSPACE_CONTEXT"),
-        )
-        .expect("Failed to parse GITPOD_WORKSPACE_CONTEXT");
-
-        Self {
-            domain: workspace_url.replace("https://", "8080-"),
-            url: workspace_url.replace("https://", "https://8080-"),
-            workspace_context,
-        }
-    }
-
-    fn is_host() -> bool {
-        std::env::var("GITPOD_WORKSPACE_URL").is_ok()
-    }
-
-    fn get_context_for(&self, name: &str) -> Option<&str> {
-        self.workspace_context
-            .envvars
-            .as_ref()
-            .and_then(|envvars| envvars.iter().find(|envvar| envvar.name == name))
-            .map(|envvar| envvar.value.as_str())
-    }
-}
-
-#[derive(Default, Clone, Eq, PartialEq, Debug)]
-struct OptionalService<'a> {
-    compose_profile: Option<Vec<&'a str>>,
-    repositories: Option<Vec<Repository>>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 enum Setting {
     SetupDatabase,
     EnableRateLimiting,
+    InstallGitHooks,
 }
 
 fn main() -> std::io::Result<()> {
@@ -213,19 +193,36 @@ fn main() -> std::io::Result<()> {
     assert!(args.len() > 1, "Missing command");
 
     let config = Config::load();
+    let preset = flag_value(&args, "--preset");
+    let locked = args.iter().any(|arg| arg == "--locked");
 
     match args[1].as_str() {
-        "setup" => setup(config, true, std::env::var("NONINTERACTIVE").is_ok()),
-        "add_services" => setup(config, false, false),
-        "hostname" => hostname(config),
+        "setup" => setup(
+            config,
+            true,
+            std::env::var("NONINTERACTIVE").is_ok(),
+            locked,
+            preset.as_deref(),
+        ),
+        "add_services" => setup(config, false, false, locked, preset.as_deref()),
+        "hostname" => hostname::run(&args[2..], config),
         "mobile" => mobile_setup(config),
-        "welcome" => welcome(config),
+        "welcome" => welcome(&config),
+        "urls" => urls(&config),
         "flutter" => flutter(config),
-        "gitpod_public" => gitpod_public(),
+        "ports" => ports(&args[2..], &config),
+        "repos" => repos::run(&args[2..], config),
         _ => panic!("Unknown command"),
     }
 }
 
+fn flag_value(args: &[String], flag: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|index| args.get(index + 1))
+        .cloned()
+}
+
 fn pwd_input(user_type: &str) -> std::io::Result<String> {
     input(format!(
         "Choose a password for {user_type} users (blank for 'password')"
@@ -237,7 +234,13 @@ fn pwd_input(user_type: &str) -> std::io::Result<String> {
 }
 
 #[allow(clippy::too_many_lines)]
-fn setup(mut config: Config, first_setup: bool, noninteractive: bool) -> std::io::Result<()> {
+fn setup(
+    mut config: Config,
+    first_setup: bool,
+    noninteractive: bool,
+    locked: bool,
+    preset: Option<&str>,
+) -> std::io::Result<()> {
     if first_setup {
         intro(BANNER)?;
     } else {
@@ -247,18 +250,63 @@ fn setup(mut config: Config, first_setup: bool, noninteractive: bool) -> std::io
         )?;
     }
 
-    let mut services: Vec<OptionalService<'static>> = vec![];
+    let cloud = cloud::detect()?;
+    let context = cloud
+        .as_deref()
+        .map(WorkspaceContext::load)
+        .transpose()?
+        .unwrap_or_default();
+    let preset = preset
+        .or(context.preset.as_deref())
+        .map(Preset::find)
+        .transpose()?;
+    let lockfile = locked.then(Lockfile::load).transpose()?;
+
+    let mut services: Vec<OptionalService> = vec![];
 
     if noninteractive {
+        if let Some(preset) = &preset {
+            services = preset.optional_services()?;
+        }
+        services = with_pull_request_services(services, &context)?;
+        review_resources(&services, true)?;
+
+        let setup_database = context.setup_database.unwrap_or(true);
         config.password = Some(DEFAULT_PASSWORD.to_string());
         config.su_password = Some(DEFAULT_PASSWORD.to_string());
-        config.setup_api_tokens = Some(true);
+        config.setup_api_tokens = Some(setup_database && context.setup_api_tokens.unwrap_or(true));
         config.enable_rate_limiting = Some(true);
-        config.setup_database = Some(true);
+        config.setup_database = Some(setup_database);
     } else {
-        services = prompt_for_services()?;
+        let preset = match preset {
+            Some(preset) => Some(preset),
+            None => prompt_for_preset()?,
+        };
+        if let Some(preset) = &preset {
+            services = preset.optional_services()?;
+        }
+        services = with_pull_request_services(services, &context)?;
+        let enabled = if first_setup {
+            vec![]
+        } else {
+            enabled_services(&config)?
+        };
+
+        loop {
+            services = prompt_for_services(&services, &enabled)?;
 
-        let options = prompt_for_options(first_setup)?;
+            let mut all_services = enabled.clone();
+            all_services.extend(services.iter().filter(|s| !enabled.contains(s)).cloned());
+            if review_resources(&all_services, false)? {
+                break;
+            }
+        }
+
+        let options = prompt_for_options(
+            first_setup,
+            context.setup_database.unwrap_or(first_setup),
+            &config,
+        )?;
 
         let (su_password, password) = if options.contains(&Setting::SetupDatabase) {
             (pwd_input("admin")?, pwd_input("regular")?)
@@ -268,7 +316,9 @@ fn setup(mut config: Config, first_setup: bool, noninteractive: bool) -> std::io
 
         config.setup_api_tokens = Some(
             options.contains(&Setting::SetupDatabase)
-                && if password != "password" || su_password != "password" {
+                && if let Some(setup_api_tokens) = context.setup_api_tokens {
+                    setup_api_tokens
+                } else if password != "password" || su_password != "password" {
                     confirm("Do you want to setup default API tokens for the admin and regular users? Will be created with `lip_{username}` format")
             .interact()?
                 } else {
@@ -276,31 +326,40 @@ fn setup(mut config: Config, first_setup: bool, noninteractive: bool) -> std::io
                 },
         );
 
+        if first_setup && config.github_username.is_none() && config.forks.is_none() {
+            config.github_username = Some(repos::prompt_for_github_username()?);
+        }
+
         config.setup_database = Some(options.contains(&Setting::SetupDatabase));
         config.enable_rate_limiting = Some(options.contains(&Setting::EnableRateLimiting));
+        config.git_hooks = Some(options.contains(&Setting::InstallGitHooks));
         config.su_password = Some(su_password);
         config.password = Some(password);
 
-        if Gitpod::is_host()
-        && confirm("By default, only this browser session can access your Gitpod development site.\nWould you like it to be accessible to other clients?")
-        .initial_value(false)
-        .interact()?
+        if let Some(cloud) = cloud
+            .as_deref()
+            .filter(|cloud| cloud.supports_public_ports())
         {
-            gitpod_public()?;
+            if confirm(format!("By default, only this browser session can access your {} development site.\nWould you like it to be accessible to other clients?", cloud.name()))
+                .initial_value(false)
+                .interact()?
+            {
+                make_lila_public(cloud)?;
+            }
         }
+    }
 
-        config.setup_bbppairings = Some(
-            services
-                .iter()
-                .any(|service| service.compose_profile == Some(vec!["swiss-pairings"])),
-        );
+    config.setup_bbppairings = Some(
+        services
+            .iter()
+            .any(|service| service.has_profile("swiss-pairings")),
+    );
 
-        config.enable_monitoring = Some(
-            services
-                .iter()
-                .any(|service| service.compose_profile == Some(vec!["monitoring"])),
-        );
-    }
+    config.enable_monitoring = Some(
+        services
+            .iter()
+            .any(|service| service.has_profile("monitoring")),
+    );
 
     let selected_profiles: Vec<String> = services
         .iter()
@@ -318,15 +377,21 @@ fn setup(mut config: Config, first_setup: bool, noninteractive: bool) -> std::io
 
     config.compose_profiles = Some(profiles);
 
-    if Gitpod::is_host() {
-        let gitpod = Gitpod::load();
-        config.lila_domain = Some(gitpod.domain);
-        config.lila_url = Some(gitpod.url);
+    config.compose_files = Some(compose_files(&services, &config, first_setup));
+
+    if let Some(url) = cloud.as_deref().and_then(|cloud| cloud.url_for_port(8080)) {
+        config.lila_domain = Some(cloud::domain_of(&url));
+        config.lila_url = Some(url);
     }
 
     config.save()?;
 
-    create_placeholder_dirs();
+    if let Some(lockfile) = &lockfile {
+        // Only for this run, the pins are not saved to settings.toml
+        lockfile.pin_refs(&mut config);
+    }
+
+    create_placeholder_dirs(&services)?;
 
     let mut repos_to_clone: Vec<Repository> = vec![
         Repository::new("lichess-org", "lila"),
@@ -345,43 +410,45 @@ fn setup(mut config: Config, first_setup: bool, noninteractive: bool) -> std::io
 
     repos_to_clone.extend(optional_repos);
 
-    for repo in repos_to_clone {
-        let progress = spinner();
-        progress.start(&format!("Cloning {}...", repo.full_name()));
+    repos::clone_all(&repos_to_clone, &config)?;
 
-        if repo.clone_path().read_dir()?.next().is_some() {
-            progress.stop(format!("✓ Already cloned {}", repo.full_name()));
-            continue;
-        }
+    // Checked once cloned, the file can live in the repository of the service
+    check_compose_files(&services)?;
 
-        let mut cmd = Command::new("git");
-        cmd.arg("clone")
-            .arg("--origin")
-            .arg("upstream")
-            .arg("--depth")
-            .arg("1")
-            .arg("--recurse-submodules")
-            .arg(repo.url())
-            .arg(repo.clone_path());
-
-        let output = cmd.output()?;
-        assert!(
-            output.status.success(),
-            "Failed to clone repo: {} - {output:?}",
-            repo.full_name()
-        );
+    if let Some(lockfile) = &lockfile {
+        lockfile.checkout_existing(&repos_to_clone, &config)?;
+    }
 
-        progress.stop(format!("✓ Cloned {}", repo.full_name()));
+    if config.git_hooks.unwrap_or_default() {
+        repos::hooks::install(&repos_to_clone)?;
     }
 
-    if Gitpod::is_host() {
-        gitpod_checkout_pr()?;
+    repos::check::preflight(&repos_to_clone, &config, !noninteractive)?;
+
+    if cloud.is_some() {
+        checkout_context_prs(&context, &config)?;
     }
 
     outro("Starting services...")
 }
 
-fn create_placeholder_dirs() {
+fn check_compose_files(services: &[OptionalService]) -> std::io::Result<()> {
+    for service in services {
+        if let Some(compose_file) = service.compose_file.as_ref().filter(|file| !file.is_file()) {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "Compose file {} for service `{}` not found",
+                    compose_file.display(),
+                    service.name
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn create_placeholder_dirs(services: &[OptionalService]) -> std::io::Result<()> {
     // Create a placeholder directory for each of the repos
     // otherwise the directories will be created by Docker
     // when the volumes are mounted and they may be owned by root
@@ -403,210 +470,226 @@ fn create_placeholder_dirs() {
         Repository::new("lichess-org", "berserk"),
         Repository::new("cyanfish", "bbpPairings"),
     ]
-    .iter()
-    .map(Repository::clone_path)
-    .for_each(|path| {
-        std::fs::create_dir_all(path).unwrap();
-    });
+    .into_iter()
+    .chain(
+        OptionalService::all()?
+            .into_iter()
+            .filter_map(|service| service.repositories)
+            .flatten(),
+    )
+    .map(|repo| repo.clone_path())
+    // Syzygy files for the tablebase service
+    .chain(
+        services
+            .iter()
+            .any(|service| service.name == "tablebase")
+            .then(|| PathBuf::from("tablebases")),
+    )
+    .try_for_each(std::fs::create_dir_all)
 }
 
-fn gitpod_checkout_pr() -> std::io::Result<()> {
-    let gitpod = Gitpod::load();
-
-    let Some(pr_no) = gitpod.get_context_for("LILA_PR") else {
-        return step("No lila PR specified, using default branch");
-    };
+/// Check out the pull requests given in the workspace context, like `LILA_PR`
+fn checkout_context_prs(context: &WorkspaceContext, config: &Config) -> std::io::Result<()> {
+    if context.pull_requests.is_empty() {
+        return step("No PR specified, using default branches");
+    }
 
-    let pr_url = format!("https://github.com/lichess-org/lila/pull/{pr_no}");
-    let branch_name = format!("pr-{pr_no}");
+    for (repo, pr_no) in &context.pull_requests {
+        let pr_url = repo.pull_request_url(*pr_no);
 
-    let progress = spinner();
-    progress.start(format!("Checking out lila PR #{pr_no}: {pr_url}..."));
-
-    let mut cmd = Command::new("git");
-    cmd.current_dir("repos/lila")
-        .arg("fetch")
-        .arg("upstream")
-        .arg(format!("pull/{pr_no}/head:{branch_name}"))
-        .arg("--depth")
-        .arg("25")
-        .arg("--recurse-submodules");
-
-    let output = cmd.output()?;
-    assert!(
-        output.status.success(),
-        "Failed to fetch upstream PR #{pr_no} - {output:?}",
-    );
+        let progress = spinner();
+        progress.start(format!(
+            "Checking out {} PR #{pr_no}: {pr_url}...",
+            repo.project
+        ));
 
-    let mut cmd = Command::new("git");
-    cmd.current_dir("repos/lila")
-        .arg("checkout")
-        .arg(&branch_name);
+        repo.checkout_pr(*pr_no, config)?;
 
-    let output = cmd.output()?;
-    assert!(
-        output.status.success(),
-        "Failed to checkout PR branch {branch_name} - {output:?}",
-    );
+        progress.stop(format!(
+            "✓ Checked out {} PR #{pr_no} - {pr_url}",
+            repo.project
+        ));
+    }
 
-    progress.stop(format!("✓ Checked out PR #{pr_no} - {pr_url}"));
     Ok(())
 }
 
-#[allow(clippy::too_many_lines)]
-fn prompt_for_services() -> Result<Vec<OptionalService<'static>>, Error> {
-    multiselect(
-        "Select which optional services to include:\n    (Use arrows, <space> to toggle, <enter> to continue)\n",
-    )
-    .required(false)
-    .item(
-        OptionalService {
-            compose_profile: vec!["mongo-express"].into(),
-            repositories: None,
-        },
-        "Database admin interface",
-        "Mongo Express for viewing database structure and data",
-    )
-    .item(
-        OptionalService {
-            compose_profile: vec!["email"].into(),
-            repositories: None,
-        },
-        "Outbound email testing",
-        "for capturing and debugging outbound email",
-    )
-    .item(
-        OptionalService {
-            compose_profile: vec!["stockfish-play"].into(),
-            repositories: vec![Repository::new("lichess-org", "lila-fishnet")].into(),
-        },
-        "Stockfish Play",
-        "for playing against the computer",
-    )
-    .item(
-        OptionalService {
-            compose_profile: vec!["stockfish-analysis"].into(),
-            repositories: None,
-        },
-        "Stockfish Analysis",
-        "for requesting computer analysis of games",
-    )
-    .item(
-        OptionalService {
-            compose_profile: vec!["external-engine"].into(),
-            repositories: vec![Repository::new("lichess-org", "lila-engine")].into(),
-        },
-        "External Engine",
-        "for connecting a local chess engine to the analysis board",
-    )
-    .item(
-        OptionalService {
-            compose_profile: None,
-            repositories: vec![Repository::new("lichess-org", "lifat")].into(),
-        },
-        "Larger static assets",
-        "Analysis board engines, background images, voice move models, etc",
-    )
-    .item(
-        OptionalService {
-            compose_profile: vec!["search"].into(),
-            repositories: vec![Repository::new("lichess-org", "lila-search")].into(),
-        },
-        "Search",
-        "for searching games, forum posts, etc",
-    )
-    .item(
-        OptionalService {
-            compose_profile: vec!["gifs"].into(),
-            repositories: vec![Repository::new("lichess-org", "lila-gif")].into(),
-        },
-        "GIF + image generation",
-        "for generating animated GIFs and screenshots of games",
-    )
-    .item(
-        OptionalService {
-            compose_profile: vec!["thumbnails"].into(),
-            repositories: None,
-        },
-        "Image uploads + thumbnails",
-        "for blog/coach/streamer images",
-    )
-    .item(
-        OptionalService {
-            compose_profile: vec!["api-docs"].into(),
-            repositories: vec![Repository::new("lichess-org", "api")].into(),
-        },
-        "API docs",
-        "standalone API documentation",
-    )
-    .item(
-        OptionalService {
-            compose_profile: vec!["chessground"].into(),
-            repositories: vec![Repository::new("lichess-org", "chessground")].into(),
-        },
-        "Chessground",
-        "standalone board UI",
-    )
-    .item(
-        OptionalService {
-            compose_profile: vec!["pgn-viewer"].into(),
-            repositories: vec![Repository::new("lichess-org", "pgn-viewer")].into(),
-        },
-        "PGN Viewer",
-        "standalone PGN viewer",
-    )
-    .item(
-        OptionalService {
-            compose_profile: None ,
-            repositories: vec![Repository::new("lichess-org", "scalachess")].into(),
-        },
-        "Scalachess",
-        "standalone chess logic library",
-    )
-    .item(
-        OptionalService {
-            compose_profile: vec!["mobile"].into(),
-            repositories: vec![Repository::new("lichess-org", "mobile")].into(),
-        },
-        "Mobile app",
-        "Flutter-based mobile app",
-    )
-    .item(
-        OptionalService {
-            compose_profile: None,
-            repositories: vec![Repository::new("lichess-org", "dartchess")].into(),
-        },
-        "Dartchess",
-        "standalone chess library for mobile platforms",
-    )
-    .item(
-        OptionalService {
-            compose_profile: None,
-            repositories: vec![Repository::new("lichess-org", "berserk")].into(),
-        },
-        "Berserk",
-        "Python API client",
-    )
-    .item(
-        OptionalService {
-            compose_profile: vec!["monitoring"].into(),
-            repositories: None,
-        },
-        "Monitoring",
-        "Metric collection using InfluxDB",
-    )
-    .item(
-        OptionalService {
-            compose_profile: vec!["swiss-pairings"].into(),
-            repositories: vec![Repository::new("cyanfish", "bbpPairings")].into(),
-        },
-        "Swiss Pairings",
-        "bbpPairings tool",
+/// Add the services that bring the repositories of the workspace PRs,
+/// like Chessground for `CHESSGROUND_PR`
+fn with_pull_request_services(
+    mut services: Vec<OptionalService>,
+    context: &WorkspaceContext,
+) -> std::io::Result<Vec<OptionalService>> {
+    for service in OptionalService::all()? {
+        let brings_pr_repo = service.repositories.iter().flatten().any(|repo| {
+            context
+                .pull_requests
+                .iter()
+                .any(|(pr_repo, _)| pr_repo.project == repo.project)
+        });
+        if brings_pr_repo && !services.contains(&service) {
+            services.push(service);
+        }
+    }
+
+    Ok(services)
+}
+
+/// Services whose compose profiles are already part of the saved configuration
+fn enabled_services(config: &Config) -> std::io::Result<Vec<OptionalService>> {
+    let profiles = config.compose_profiles.clone().unwrap_or_default();
+
+    Ok(OptionalService::all()?
+        .into_iter()
+        .filter(|service| {
+            service
+                .compose_profile
+                .as_ref()
+                .is_some_and(|compose_profile| {
+                    compose_profile
+                        .iter()
+                        .all(|profile| profiles.iter().any(|p| p == profile))
+                })
+        })
+        .collect())
+}
+
+fn prompt_for_preset() -> std::io::Result<Option<Preset>> {
+    let presets = Preset::all()?;
+
+    let selected = presets
+        .iter()
+        .fold(
+            select("Start from a preset?").item(
+                String::new(),
+                "No preset",
+                "pick the optional services individually",
+            ),
+            |prompt, preset| prompt.item(preset.name.clone(), &preset.name, &preset.description),
+        )
+        .interact()?;
+
+    Ok(presets.into_iter().find(|preset| preset.name == selected))
+}
+
+/// The compose files of the selected services. The worktrees override is kept as long as
+/// worktrees are mounted, even when the previous list is replaced on a first setup.
+fn compose_files(services: &[OptionalService], config: &Config, first_setup: bool) -> Vec<String> {
+    let previous = config.compose_files.clone().unwrap_or_default();
+    let worktrees_mounted = config
+        .worktrees
+        .as_ref()
+        .is_some_and(|worktrees| !worktrees.is_empty());
+
+    let mut compose_files: Vec<String> = services
+        .iter()
+        .filter_map(|service| service.compose_file.as_ref())
+        .map(|compose_file| compose_file.display().to_string())
+        .collect();
+    compose_files.extend(previous.into_iter().filter(|file| {
+        if file == repos::WORKTREES_OVERRIDE {
+            worktrees_mounted
+        } else {
+            !first_setup
+        }
+    }));
+    compose_files.sort();
+    compose_files.dedup();
+
+    compose_files
+}
+
+/// The hint of each service shows its cost. The prompt shows what the services that are always
+/// running need, the total with the current selection and what this machine has.
+/// cliclack can't update the prompt while toggling, so the total is that of the selection the
+/// prompt starts from, and it's refreshed each time the selection is changed after the review.
+fn prompt_for_services(
+    initial_values: &[OptionalService],
+    enabled: &[OptionalService],
+) -> Result<Vec<OptionalService>, Error> {
+    let budget = std::iter::once(format!(
+        "    Base and already enabled services: ~{}",
+        Resources::total(enabled)
+    ))
+    .chain((!initial_values.is_empty()).then(|| {
+        format!(
+            "    With the current selection: ~{}",
+            Resources::total(
+                enabled.iter().chain(
+                    initial_values
+                        .iter()
+                        .filter(|service| !enabled.contains(service))
+                )
+            )
+        )
+    }))
+    .chain(
+        HostCapacity::detect()
+            .summary()
+            .map(|capacity| format!("    This machine: {capacity}")),
     )
-    .interact()
+    .collect::<Vec<_>>()
+    .join("\n");
+
+    OptionalService::all()?
+        .into_iter()
+        .fold(
+            multiselect(format!(
+                "Select which optional services to include:\n{budget}\n    (Use arrows, <space> to toggle, <enter> to continue)\n",
+            ))
+            .required(false),
+            |prompt, service| {
+                let label = service.label.clone();
+                let hint = if service.resources.is_zero() {
+                    service.description.clone()
+                } else {
+                    format!("{} (~{})", service.description, service.resources)
+                };
+                prompt.item(service, label, hint)
+            },
+        )
+        .initial_values(initial_values.to_vec())
+        .interact()
+}
+
+/// Show the estimated cost of the selection and warn when this machine can't provide it.
+/// Returns `false` if the user wants to change their selection.
+fn review_resources(services: &[OptionalService], noninteractive: bool) -> std::io::Result<bool> {
+    let required = Resources::total(services);
+    let shortfalls = HostCapacity::detect().shortfalls(required);
+
+    if shortfalls.is_empty() {
+        info(format!("Estimated requirements: {required}"))?;
+        return Ok(true);
+    }
+
+    warning(format!(
+        "Estimated requirements: {required}\nThe selected services may not fit on this machine:\n  - {}",
+        shortfalls.join("\n  - ")
+    ))?;
+
+    if noninteractive {
+        return Ok(true);
+    }
+
+    confirm("Continue with this selection anyway?")
+        .initial_value(false)
+        .interact()
 }
 
-fn prompt_for_options(first_setup: bool) -> Result<Vec<Setting>, Error> {
+fn prompt_for_options(
+    first_setup: bool,
+    seed_database: bool,
+    config: &Config,
+) -> Result<Vec<Setting>, Error> {
+    let mut initial_values = vec![Setting::EnableRateLimiting];
+    if seed_database {
+        initial_values.insert(0, Setting::SetupDatabase);
+    }
+    if config.git_hooks.unwrap_or_default() {
+        initial_values.push(Setting::InstallGitHooks);
+    }
+
     multiselect("Select options:\n")
         .required(false)
         .item(
@@ -623,49 +706,13 @@ fn prompt_for_options(first_setup: bool) -> Result<Vec<Setting>, Error> {
             "Enable rate limiting",
             "To be prod-like. Can be disabled for development/testing purposes",
         )
-        .initial_values(if first_setup {
-            vec![Setting::SetupDatabase, Setting::EnableRateLimiting]
-        } else {
-            vec![Setting::EnableRateLimiting]
-        })
-        .interact()
-}
-
-fn hostname(mut config: Config) -> std::io::Result<()> {
-    if Gitpod::is_host() {
-        return error("Setting of hostname not available on Gitpod");
-    }
-
-    let local_ip = match local_ip() {
-        Ok(ip) => ip.to_string(),
-        _ => "127.0.0.1".to_string(),
-    };
-
-    let hostname: String = match select("Select a hostname to access your local Lichess instance:")
-        .initial_value("localhost")
-        .item("localhost", "localhost", "default")
-        .item(
-            local_ip.as_str(),
-            local_ip.as_str(),
-            "Your private IP address, for accessing from other devices on your local network",
-        )
         .item(
-            "10.0.2.2",
-            "10.0.2.2",
-            "For accessing from an Android emulator running on this machine",
+            Setting::InstallGitHooks,
+            "Install git hooks that check formatting",
+            "Runs `./lila-docker format` before pushes. Manage with `./lila-docker hooks`",
         )
-        .item("other", "Other", "Enter a custom hostname")
-        .interact()?
-    {
-        "other" => input("Enter a custom hostname:  (It must be resolvable)").interact()?,
-        selection => selection.to_string(),
-    };
-
-    config.lila_domain = Some(format!("{hostname}:8080"));
-    config.lila_url = Some(format!("http://{hostname}:8080"));
-    config.save()?;
-
-    outro(format!("✔ Local Lichess URL set to http://{hostname}:8080"))
+        .initial_values(initial_values)
+        .interact()
 }
 
 fn mobile_setup(mut config: Config) -> std::io::Result<()> {
@@ -707,28 +754,29 @@ fn validate_string_length(input: &str, length: usize) -> Result<(), String> {
     }
 }
 
-fn welcome(config: Config) -> std::io::Result<()> {
+fn welcome(config: &Config) -> std::io::Result<()> {
     intro("Your Lichess development environment is starting!")?;
 
     note(
         "Your development site will be available at:",
         config
             .lila_url
+            .clone()
             .unwrap_or("http://localhost:8080".to_owned()),
     )?;
 
-    if Gitpod::is_host() {
-        note(
-            "For full documentation, see:",
-            "https://lichess-org.github.io/lila-gitpod/",
-        )?;
-    } else {
-        note(
-            "For full documentation, see:",
-            "https://github.com/lichess-org/lila-docker",
-        )?;
+    let dashboard = service_dashboard(config)?;
+    if !dashboard.is_empty() {
+        note("Other services:", dashboard)?;
     }
 
+    note(
+        "For full documentation, see:",
+        cloud::detect()?.map_or("https://github.com/lichess-org/lila-docker", |cloud| {
+            cloud.docs_url()
+        }),
+    )?;
+
     note(
         "To monitor the progress:",
         "docker compose logs lila --follow",
@@ -737,12 +785,70 @@ fn welcome(config: Config) -> std::io::Result<()> {
     outro("🚀")
 }
 
+fn urls(config: &Config) -> std::io::Result<()> {
+    intro("Service URLs")?;
+
+    let lila = ServiceUrl::new("Main lila instance", 8080, "/");
+    let lila = format_service_url(cloud::detect()?.as_deref(), config, &lila);
+    let dashboard = service_dashboard(config)?;
+
+    note(
+        "Available services:",
+        if dashboard.is_empty() {
+            lila
+        } else {
+            format!("{lila}\n{dashboard}")
+        },
+    )?;
+
+    outro("Only services whose containers are running will respond")
+}
+
+/// One line per URL exposed by the enabled optional services
+fn service_dashboard(config: &Config) -> std::io::Result<String> {
+    let cloud = cloud::detect()?;
+    Ok(enabled_services(config)?
+        .iter()
+        .flat_map(|service| &service.urls)
+        .map(|url| format_service_url(cloud.as_deref(), config, url))
+        .collect::<Vec<String>>()
+        .join("\n"))
+}
+
+fn format_service_url(
+    cloud: Option<&dyn CloudEnvironment>,
+    config: &Config,
+    url: &ServiceUrl,
+) -> String {
+    let address = match cloud.and_then(|cloud| cloud.url_for_port(url.port)) {
+        Some(port_url) => format!("{port_url}{}", url.path),
+        None => local_service_url(config.lila_domain.as_deref(), url),
+    };
+
+    match &url.credentials {
+        Some(credentials) => format!("{:<22} {address} ({credentials})", url.description),
+        None => format!("{:<22} {address}", url.description),
+    }
+}
+
+/// The URL of a service port on the configured hostname (`localhost` by default)
+fn local_service_url(lila_domain: Option<&str>, url: &ServiceUrl) -> String {
+    let hostname = lila_domain.map_or("localhost", hostname::of_domain);
+
+    format!("http://{hostname}:{}{}", url.port, url.path)
+}
+
 fn flutter(config: Config) -> std::io::Result<()> {
-    let url = if Gitpod::is_host() {
-        gitpod_public()?;
-        Gitpod::load().url
-    } else {
-        config.lila_url.expect("Missing lila_url")
+    let cloud = cloud::detect()?;
+    let url = match cloud
+        .as_deref()
+        .and_then(|cloud| Some((cloud, cloud.url_for_port(8080)?)))
+    {
+        Some((cloud, url)) => {
+            make_lila_public(cloud)?;
+            url
+        }
+        None => config.lila_url.expect("Missing lila_url"),
     };
 
     if url.contains("localhost") {
@@ -756,32 +862,128 @@ fn flutter(config: Config) -> std::io::Result<()> {
     Ok(())
 }
 
-fn gitpod_public() -> std::io::Result<()> {
-    if !Gitpod::is_host() {
-        return Err(std::io::Error::new(
-            ErrorKind::Other,
-            "This command is only available on Gitpod",
-        ));
-    }
-
+/// Let other clients than the current browser session access the development site
+fn make_lila_public(cloud: &dyn CloudEnvironment) -> std::io::Result<()> {
     let progress = spinner();
     progress.start("Making http port 8080 publicly accessible...");
 
-    let mut cmd = Command::new("gp");
-    cmd.arg("ports").arg("visibility").arg("8080:public");
+    if let Err(err) = cloud.set_port_visibility(8080, Visibility::Public) {
+        progress.error(format!("✗ {err}"));
+        return Err(err);
+    }
+
+    progress.stop("✓ Port 8080 is now publicly accessible");
+    Ok(())
+}
+
+/// The development site and the ports of the enabled services, with what they serve
+fn service_ports(config: &Config) -> std::io::Result<Vec<(u16, String)>> {
+    let mut ports = vec![(8080, "Main lila instance".to_string())];
+
+    for url in enabled_services(config)?
+        .iter()
+        .flat_map(|service| &service.urls)
+    {
+        if !ports.iter().any(|(port, _)| *port == url.port) {
+            ports.push((url.port, url.description.clone()));
+        }
+    }
+
+    Ok(ports)
+}
+
+/// `all`, some port numbers, or the development site when none are given
+fn selected_ports(args: &[String], service_ports: &[(u16, String)]) -> std::io::Result<Vec<u16>> {
+    match args {
+        [] => Ok(vec![8080]),
+        [all] if all == "all" => Ok(service_ports.iter().map(|(port, _)| *port).collect()),
+        ports => ports
+            .iter()
+            .map(|port| {
+                port.parse().map_err(|_| {
+                    Error::new(ErrorKind::InvalidInput, format!("Invalid port: {port}"))
+                })
+            })
+            .collect(),
+    }
+}
+
+fn list_ports(
+    cloud: &dyn CloudEnvironment,
+    service_ports: &[(u16, String)],
+) -> std::io::Result<()> {
+    let statuses = cloud.port_statuses()?;
+
+    let rows: Vec<String> = service_ports
+        .iter()
+        .map(|(port, description)| {
+            let visibility = statuses
+                .iter()
+                .find(|status| status.port == *port)
+                .and_then(|status| status.visibility)
+                .map_or("not served".to_string(), |visibility| {
+                    visibility.to_string()
+                });
+            format!(
+                "{port:<6} {description:<22} {visibility:<11} {}",
+                cloud.url_for_port(*port).unwrap_or_default()
+            )
+        })
+        .collect();
 
-    let output = cmd.output().expect("Command failed");
-    let stdout = String::from_utf8(output.stdout).expect("Failed to parse stdout");
+    note("Ports of the enabled services", rows.join("\n"))?;
+    outro("Change them with `./lila-docker ports <public|private> [all|port...]`")
+}
 
-    if !stdout.contains("port 8080 is now public") {
-        return Err(std::io::Error::new(
-            ErrorKind::Other,
-            "Failed to make port 8080 public",
+/// `ports [list]` and `ports <public|private> [all|port...]`
+fn ports(args: &[String], config: &Config) -> std::io::Result<()> {
+    let Some(cloud) = cloud::detect()?.filter(|cloud| cloud.supports_public_ports()) else {
+        return Err(Error::other(
+            "Port visibility can only be changed on Gitpod and GitHub Codespaces",
         ));
+    };
+    let service_ports = service_ports(config)?;
+
+    let visibility = match args.first().map(String::as_str) {
+        None | Some("list") => {
+            intro(format!("Ports on {}", cloud.name()))?;
+            return list_ports(cloud.as_ref(), &service_ports);
+        }
+        Some("public") => Visibility::Public,
+        Some("private") => Visibility::Private,
+        Some(_) => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Usage: ports [list] | ports <public|private> [all|port...]",
+            ))
+        }
+    };
+    let ports = selected_ports(&args[1..], &service_ports)?;
+
+    intro(format!("Making ports {visibility}..."))?;
+
+    let mut failed = 0;
+    for port in &ports {
+        match cloud.set_port_visibility(*port, visibility) {
+            Ok(()) => step(format!("✓ Port {port} is now {visibility}"))?,
+            Err(err) => {
+                failed += 1;
+                error(format!("✗ Port {port}: {err}"))?;
+            }
+        }
     }
 
-    progress.stop("✓ Port 8080 is now publicly accessible");
-    outro(Gitpod::load().url)
+    if failed > 0 {
+        return Err(Error::other(format!(
+            "Could not change the visibility of {failed} of {} ports",
+            ports.len()
+        )));
+    }
+
+    match cloud.url_for_port(8080) {
+        Some(url) if ports.contains(&8080) => outro(url),
+        _ => outro("Done"),
+    }
 }
 
 #[cfg(test)]
@@ -789,13 +991,61 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_repository() {
-        let repo = Repository::new("lichess-org", "lila");
-        assert_eq!(repo.org, "lichess-org");
-        assert_eq!(repo.project, "lila");
-        assert_eq!(repo.full_name(), "lichess-org/lila");
-        assert_eq!(repo.url(), "https://github.com/lichess-org/lila");
-        assert_eq!(repo.clone_path(), Path::new("repos/lila"));
+    fn test_selected_ports() {
+        let service_ports = vec![
+            (8080, "Main lila instance".to_string()),
+            (8025, "Mailpit".to_string()),
+        ];
+        let args = |args: &[&str]| args.iter().map(ToString::to_string).collect::<Vec<_>>();
+
+        assert_eq!(selected_ports(&[], &service_ports).unwrap(), vec![8080]);
+        assert_eq!(
+            selected_ports(&args(&["all"]), &service_ports).unwrap(),
+            vec![8080, 8025]
+        );
+        assert_eq!(
+            selected_ports(&args(&["8025", "9663"]), &service_ports).unwrap(),
+            vec![8025, 9663]
+        );
+        assert!(selected_ports(&args(&["mailpit"]), &service_ports).is_err());
+    }
+
+    #[test]
+    fn test_compose_files_keep_worktrees_override() {
+        let services = vec![OptionalService {
+            compose_file: Some("compose/helper.yml".into()),
+            ..Default::default()
+        }];
+        let mut config = Config {
+            compose_files: Some(vec![
+                "compose/old.yml".to_string(),
+                repos::WORKTREES_OVERRIDE.to_string(),
+            ]),
+            worktrees: Some(BTreeMap::from([(
+                "lila".to_string(),
+                "feature".to_string(),
+            )])),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            compose_files(&services, &config, true),
+            vec!["compose/helper.yml", repos::WORKTREES_OVERRIDE]
+        );
+        assert_eq!(
+            compose_files(&services, &config, false),
+            vec![
+                "compose/helper.yml",
+                "compose/old.yml",
+                repos::WORKTREES_OVERRIDE
+            ]
+        );
+
+        config.worktrees = Some(BTreeMap::new());
+        assert_eq!(
+            compose_files(&services, &config, false),
+            vec!["compose/helper.yml", "compose/old.yml"]
+        );
     }
 
     #[test]
@@ -805,9 +1055,11 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::unreadable_literal)]
     fn test_set_env_vars_from_struct() {
         let contents = Config {
             compose_profiles: Some(vec!["foo".to_string(), "bar".to_string()]),
+            compose_files: None,
             setup_database: Some(true),
             setup_bbppairings: Some(false),
             enable_monitoring: Some(false),
@@ -817,10 +1069,20 @@ mod tests {
             setup_api_tokens: Some(false),
             lila_domain: Some("baz:8080".to_string()),
             lila_url: Some("http://baz:8080".to_string()),
+            recent_hostnames: None,
             phone_ip: Some("1.2.3.4".to_string()),
             connection_port: Some(1234),
             pairing_code: Some(901234),
             pairing_port: Some(5678),
+            github_username: None,
+            clone_depth: None,
+            mirror: None,
+            mirror_reference: None,
+            git_hooks: None,
+            forks: None,
+            clone_depths: None,
+            refs: None,
+            worktrees: None,
         }
         .to_env();
 
@@ -846,10 +1108,52 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_env_includes_compose_files() {
+        let contents = Config {
+            compose_profiles: Some(vec!["foo".to_string()]),
+            compose_files: Some(vec!["extra.yml".to_string()]),
+            ..Default::default()
+        }
+        .to_env();
+
+        assert_eq!(
+            contents,
+            [
+                "COMPOSE_PROFILES=foo",
+                "COMPOSE_FILE=docker-compose.yml:extra.yml"
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_env_includes_lila_config_of_enabled_services() {
+        let contents = Config {
+            compose_profiles: Some(vec!["explorer".to_string(), "push".to_string()]),
+            lila_url: Some("http://baz:8080".to_string()),
+            ..Default::default()
+        }
+        .to_env();
+
+        assert_eq!(
+            contents,
+            [
+                "COMPOSE_PROFILES=explorer,push",
+                "LILA_URL=http://baz:8080",
+                "PUSH_WEB_URL=http://lila_push:9054",
+                "EXPLORER_ENDPOINT=http://baz:8080/_explorer",
+                "EXPLORER_INTERNAL_ENDPOINT=http://lila_openingexplorer:9002",
+            ]
+            .join("\n")
+        );
+    }
+
     #[test]
     fn test_env_removes_empty_lines() {
         let contents = Config {
             compose_profiles: None,
+            compose_files: None,
             setup_database: None,
             setup_bbppairings: None,
             enable_monitoring: None,
@@ -859,16 +1163,26 @@ mod tests {
             setup_api_tokens: None,
             lila_domain: Some("baz:8080".to_string()),
             lila_url: Some("http://baz:8080".to_string()),
+            recent_hostnames: None,
             phone_ip: None,
             connection_port: None,
             pairing_code: None,
             pairing_port: None,
+            github_username: None,
+            clone_depth: None,
+            mirror: None,
+            mirror_reference: None,
+            git_hooks: None,
+            forks: None,
+            clone_depths: None,
+            refs: None,
+            worktrees: None,
         }
         .to_env();
 
         assert_eq!(
             contents,
-            vec![
+            [
                 "COMPOSE_PROFILES=",
                 "LILA_DOMAIN=baz:8080",
                 "LILA_URL=http://baz:8080"
@@ -878,76 +1192,19 @@ mod tests {
     }
 
     #[test]
-    fn test_gitpod_lila_url() {
-        std::env::set_var(
-            "GITPOD_WORKSPACE_URL",
-            "https://lichessorg-liladocker-abc123.ws-us123.gitpod.io",
-        );
-        std::env::set_var("GITPOD_WORKSPACE_CONTEXT", "{}");
-
-        let gitpod = Gitpod::load();
+    fn test_local_service_url() {
+        let url = ServiceUrl::new("Chessground", 8090, "/demo.html");
         assert_eq!(
-            gitpod.domain,
-            "8080-lichessorg-liladocker-abc123.ws-us123.gitpod.io"
+            local_service_url(None, &url),
+            "http://localhost:8090/demo.html"
         );
         assert_eq!(
-            gitpod.url,
-            "https://8080-lichessorg-liladocker-abc123.ws-us123.gitpod.io"
+            local_service_url(Some("192.168.1.2:8080"), &url),
+            "http://192.168.1.2:8090/demo.html"
         );
-        assert_eq!(gitpod.workspace_context, GitpodWorkspaceContext::default());
-        assert_eq!(gitpod.get_context_for("LILA_PR"), None);
-    }
-
-    #[test]
-    fn test_gitpod_lila_url_with_pr_context() {
-        std::env::set_var(
-            "GITPOD_WORKSPACE_URL",
-            "https://lichessorg-liladocker-abc123.ws-us123.gitpod.io",
-        );
-        std::env::set_var(
-            "GITPOD_WORKSPACE_CONTEXT",
-            r#"{"envvars":[{"name":"LILA_PR","value":"12345"}]}"#,
-        );
-
-        let gitpod = Gitpod::load();
-
-        assert_eq!(
-            gitpod.workspace_context,
-            GitpodWorkspaceContext {
-                envvars: Some(vec![GitpodEnvVar {
-                    name: "LILA_PR".to_string(),
-                    value: "12345".to_string(),
-                }])
-            }
-        );
-
-        assert_eq!(gitpod.get_context_for("LILA_PR"), Some("12345"));
-    }
-
-    #[test]
-    fn test_gitpod_lila_url_with_context_but_no_pr() {
-        std::env::set_var(
-            "GITPOD_WORKSPACE_URL",
-            "https://lichessorg-liladocker-abc123.ws-us123.gitpod.io",
-        );
-        std::env::set_var(
-            "GITPOD_WORKSPACE_CONTEXT",
-            r#"{"envvars":[{"name":"FOO","value":"BAR"}]}"#,
-        );
-
-        let gitpod = Gitpod::load();
-
         assert_eq!(
-            gitpod.workspace_context,
-            GitpodWorkspaceContext {
-                envvars: Some(vec![GitpodEnvVar {
-                    name: "FOO".to_string(),
-                    value: "BAR".to_string(),
-                }])
-            }
+            local_service_url(Some("lichess.local"), &url),
+            "http://lichess.local:8090/demo.html"
         );
-
-        assert_eq!(gitpod.get_context_for("FOO"), Some("BAR"));
-        assert_eq!(gitpod.get_context_for("LILA_PR"), None);
     }
 }
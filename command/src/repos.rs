@@ -0,0 +1,995 @@
+use crate::{services::OptionalService, Config};
+use cliclack::{
+    input, intro,
+    log::{info, step, warning},
+    multi_progress, note, outro, spinner,
+};
+use std::{
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+mod branch;
+pub mod check;
+mod checkout;
+mod doctor;
+pub mod hooks;
+mod lock;
+mod mirror;
+mod status;
+mod sync;
+mod worktree;
+
+pub use lock::Lockfile;
+pub use worktree::WORKTREES_OVERRIDE;
+
+const MAX_PARALLEL_JOBS: usize = 4;
+const CLONE_ATTEMPTS: u32 = 3;
+const DEFAULT_CLONE_DEPTH: u32 = 1;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Repository {
+    pub org: String,
+    pub project: String,
+    url: Option<String>,
+    clone_path: Option<PathBuf>,
+}
+
+impl Repository {
+    pub fn new(org: &str, project: &str) -> Self {
+        Self {
+            org: org.to_string(),
+            project: project.to_string(),
+            url: None,
+            clone_path: None,
+        }
+    }
+
+    /// A repository hosted anywhere, e.g. `https://git.example.com/team/project.git`
+    /// or `git@github.com:team/project.git`
+    pub fn from_url(url: &str, clone_path: Option<PathBuf>) -> Self {
+        let mut segments = url
+            .trim_end_matches('/')
+            .trim_end_matches(".git")
+            .rsplit(['/', ':']);
+        let project = segments.next().unwrap_or_default();
+        let org = segments.next().unwrap_or_default();
+
+        Self {
+            url: Some(url.to_string()),
+            clone_path,
+            ..Self::new(org, project)
+        }
+    }
+
+    /// Repositories that are part of every setup
+    pub fn core() -> Vec<Self> {
+        vec![
+            Self::new("lichess-org", "lila"),
+            Self::new("lichess-org", "lila-ws"),
+            Self::new("lichess-org", "lila-db-seed"),
+        ]
+    }
+
+    /// Every repository lila-docker knows about, whether it has been cloned or not
+    pub fn all() -> std::io::Result<Vec<Self>> {
+        let mut repos = Self::core();
+
+        for repo in OptionalService::all()?
+            .into_iter()
+            .filter_map(|service| service.repositories)
+            .flatten()
+        {
+            if !repos.contains(&repo) {
+                repos.push(repo);
+            }
+        }
+
+        Ok(repos)
+    }
+
+    /// Look up a repository by project name (`lila`) or full name (`lichess-org/lila`)
+    pub fn find(name: &str) -> std::io::Result<Self> {
+        Self::all()?
+            .into_iter()
+            .find(|repo| repo.project == name || repo.full_name() == name)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("Unknown repository `{name}`")))
+    }
+
+    pub fn full_name(&self) -> String {
+        format!("{}/{}", self.org, self.project)
+    }
+
+    pub fn url(&self) -> String {
+        self.url
+            .clone()
+            .unwrap_or_else(|| format!("https://github.com/{}/{}", self.org, self.project))
+    }
+
+    pub fn clone_path(&self) -> PathBuf {
+        self.clone_path
+            .clone()
+            .unwrap_or_else(|| Path::new("repos").join(&self.project))
+    }
+
+    pub fn is_cloned(&self) -> bool {
+        self.clone_path().join(".git").exists()
+    }
+
+    /// The user's fork of this repository: an explicit URL from the `forks` setting,
+    /// otherwise a GitHub repository with the same name under `github_username`
+    pub fn fork_url(&self, config: &Config) -> Option<String> {
+        config
+            .forks
+            .as_ref()
+            .and_then(|forks| forks.get(&self.project))
+            .cloned()
+            .or_else(|| {
+                config
+                    .github_username
+                    .as_ref()
+                    .filter(|username| !username.is_empty())
+                    .map(|username| format!("https://github.com/{username}/{}", self.project))
+            })
+    }
+
+    /// Run a git command inside the checkout and return its stdout
+    pub fn git(&self, args: &[&str]) -> std::io::Result<String> {
+        git_in(&self.clone_path(), args)
+    }
+
+    fn is_shallow(&self) -> std::io::Result<bool> {
+        Ok(self.git(&["rev-parse", "--is-shallow-repository"])? == "true")
+    }
+
+    /// `upstream` for repositories cloned by lila-docker, otherwise `origin`
+    fn main_remote(&self) -> &'static str {
+        if self.remote_url("upstream").is_some() {
+            "upstream"
+        } else {
+            "origin"
+        }
+    }
+
+    /// Fetch the full history of the repository and its submodules. A shallow clone
+    /// only tracks the default branch, so all the branches are fetched as well.
+    fn unshallow(&self) -> std::io::Result<()> {
+        let remote = self.main_remote();
+
+        self.git(&[
+            "config",
+            &format!("remote.{remote}.fetch"),
+            &format!("+refs/heads/*:refs/remotes/{remote}/*"),
+        ])?;
+
+        if self.is_shallow()? {
+            self.git(&["fetch", "--unshallow", remote])?;
+        } else {
+            self.git(&["fetch", remote])?;
+        }
+
+        self.git(&[
+            "submodule",
+            "foreach",
+            "--recursive",
+            "if [ \"$(git rev-parse --is-shallow-repository)\" = true ]; then git fetch --unshallow; fi",
+        ])?;
+
+        Ok(())
+    }
+
+    fn remote_url(&self, remote: &str) -> Option<String> {
+        self.git(&["remote", "get-url", remote]).ok()
+    }
+
+    /// Point the `origin` remote at the user's fork and push there by default,
+    /// leaving `upstream` on the original repository.
+    /// Returns the fork URL, or `None` if no fork is configured.
+    pub fn add_fork_remote(&self, config: &Config) -> std::io::Result<Option<String>> {
+        let Some(fork_url) = self.fork_url(config) else {
+            return Ok(None);
+        };
+
+        match self.remote_url("origin") {
+            Some(url) if url == fork_url => {}
+            Some(_) => {
+                self.git(&["remote", "set-url", "origin", &fork_url])?;
+            }
+            None => {
+                self.git(&["remote", "add", "origin", &fork_url])?;
+            }
+        }
+
+        self.git(&["config", "remote.pushDefault", "origin"])?;
+
+        Ok(Some(fork_url))
+    }
+
+    fn checkout_state(&self) -> CheckoutState {
+        let path = self.clone_path();
+
+        if !path
+            .read_dir()
+            .is_ok_and(|mut entries| entries.next().is_some())
+        {
+            return CheckoutState::Empty;
+        }
+
+        if path.join(".git").exists() && self.git(&["rev-parse", "--verify", "HEAD"]).is_ok() {
+            CheckoutState::Valid
+        } else {
+            CheckoutState::Broken
+        }
+    }
+
+    /// Keep whatever is in a broken checkout out of the way instead of deleting it,
+    /// in case it holds someone's work
+    fn move_broken_checkout_aside(&self) -> std::io::Result<PathBuf> {
+        let path = self.clone_path();
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut destination = path.clone().into_os_string();
+        destination.push(format!(".broken-{timestamp}"));
+
+        std::fs::rename(&path, &destination)?;
+        std::fs::create_dir_all(&path)?;
+
+        Ok(destination.into())
+    }
+
+    /// Remove what a failed `git clone` left behind, keeping the (empty) placeholder directory
+    fn remove_partial_clone(&self) -> std::io::Result<()> {
+        let path = self.clone_path();
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+        std::fs::create_dir_all(&path)
+    }
+
+    /// Number of commits to clone, or `None` for the full history.
+    /// Set per repository with `clone_depths`, or for all of them with `clone_depth`
+    /// (`0` means full history).
+    pub fn clone_depth(&self, config: &Config) -> Option<u32> {
+        let depth = config
+            .clone_depths
+            .as_ref()
+            .and_then(|depths| depths.get(&self.project))
+            .copied()
+            .or(config.clone_depth)
+            .unwrap_or(DEFAULT_CLONE_DEPTH);
+
+        (depth > 0).then_some(depth)
+    }
+
+    /// Branch, tag or commit SHA to clone instead of the default branch, set with `refs`
+    pub fn pinned_ref(&self, config: &Config) -> Option<String> {
+        config
+            .refs
+            .as_ref()
+            .and_then(|refs| refs.get(&self.project))
+            .cloned()
+    }
+
+    fn git_clone(&self, config: &Config) -> Result<(), CloneError> {
+        let pinned_ref = self.pinned_ref(config);
+        let mirror = self.available_mirror(config);
+        let mut cmd = Command::new("git");
+        cmd.arg("clone").arg("--origin").arg("upstream");
+
+        if let Some(depth) = self.clone_depth(config) {
+            cmd.arg("--depth").arg(depth.to_string());
+        }
+
+        // Commits cannot be cloned directly, they are fetched after cloning the default branch
+        if let Some(branch_or_tag) = pinned_ref.as_ref().filter(|r| !is_commit_sha(r)) {
+            cmd.arg("--branch").arg(branch_or_tag);
+        }
+
+        let source = if let Some(mirror) = &mirror {
+            if config.mirror_reference.unwrap_or_default() {
+                cmd.arg("--reference").arg(mirror);
+            }
+            mirror::file_url(mirror).map_err(|err| CloneError::permanent(err.to_string()))?
+        } else {
+            // Submodules of mirrored clones are set up by `finish_mirror_clone`
+            cmd.arg("--recurse-submodules");
+            self.url()
+        };
+
+        let output = cmd
+            .arg(source)
+            .arg(self.clone_path())
+            .output()
+            .map_err(|err| CloneError::permanent(err.to_string()))?;
+
+        if !output.status.success() {
+            return Err(CloneError::from_stderr(&String::from_utf8_lossy(
+                &output.stderr,
+            )));
+        }
+
+        let sha = pinned_ref.filter(|r| is_commit_sha(r));
+        if mirror.is_some() {
+            self.finish_mirror_clone(config, sha.as_deref())
+        } else if let Some(sha) = sha {
+            self.checkout_commit(&sha, self.clone_depth(config))
+        } else {
+            Ok(())
+        }
+        .map_err(|err| CloneError::from_stderr(&err.to_string()))
+    }
+
+    /// Fetch a single commit if needed and check it out with its submodules, on a detached HEAD
+    pub fn checkout_commit(&self, sha: &str, depth: Option<u32>) -> std::io::Result<()> {
+        self.checkout_detached(sha, depth)?;
+        self.git(&["submodule", "update", "--init", "--recursive"])?;
+        Ok(())
+    }
+
+    /// Fetch a single commit if needed and check it out on a detached HEAD, without submodules
+    fn checkout_detached(&self, sha: &str, depth: Option<u32>) -> std::io::Result<()> {
+        if self
+            .git(&["cat-file", "-e", &format!("{sha}^{{commit}}")])
+            .is_err()
+        {
+            let depth = depth.map(|depth| depth.to_string());
+            let mut fetch = vec!["fetch", "--quiet", self.main_remote(), sha];
+            if let Some(depth) = &depth {
+                fetch.extend(["--depth", depth]);
+            }
+            self.git(&fetch)?;
+        }
+
+        self.git(&["checkout", "--quiet", "--detach", sha])?;
+        Ok(())
+    }
+
+    fn clone_into_place(&self, config: &Config, report: impl Fn(&str)) -> CloneStatus {
+        let moved_to = match self.checkout_state() {
+            CheckoutState::Valid => return CloneStatus::Skipped,
+            CheckoutState::Empty => None,
+            CheckoutState::Broken => {
+                report("found a broken checkout, moving it aside...");
+                match self.move_broken_checkout_aside() {
+                    Ok(moved_to) => Some(moved_to),
+                    Err(err) => return CloneStatus::Failed(err.to_string()),
+                }
+            }
+        };
+
+        for attempt in 1..=CLONE_ATTEMPTS {
+            if attempt == 1 {
+                report("cloning...");
+            } else {
+                report(&format!("retrying ({attempt}/{CLONE_ATTEMPTS})..."));
+            }
+
+            match self.git_clone(config) {
+                Ok(()) => break,
+                Err(CloneError { reason, transient }) => {
+                    if let Err(err) = self.remove_partial_clone() {
+                        return CloneStatus::Failed(format!(
+                            "{reason} (and could not clean up: {err})"
+                        ));
+                    }
+                    // A missing ref or a denied access fails the same way every time
+                    if attempt == CLONE_ATTEMPTS || !transient {
+                        return CloneStatus::Failed(reason);
+                    }
+                    std::thread::sleep(retry_delay(attempt));
+                }
+            }
+        }
+
+        report("adding fork remote...");
+
+        match (self.add_fork_remote(config), moved_to) {
+            (Err(err), _) => CloneStatus::Failed(err.to_string()),
+            (Ok(_), Some(moved_to)) => CloneStatus::Recloned(moved_to),
+            (Ok(_), None) => CloneStatus::Cloned,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CheckoutState {
+    /// Missing or empty directory
+    Empty,
+    Valid,
+    /// Not a git repository, or one without a valid HEAD (e.g. an interrupted clone)
+    Broken,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CloneStatus {
+    Cloned,
+    /// Cloned again after moving a broken checkout to the given path
+    Recloned(PathBuf),
+    Skipped,
+    Failed(String),
+}
+
+/// Why a clone failed, and whether trying again may help
+struct CloneError {
+    reason: String,
+    transient: bool,
+}
+
+impl CloneError {
+    fn from_stderr(stderr: &str) -> Self {
+        Self {
+            reason: git_error_summary(stderr),
+            transient: is_transient_git_error(stderr),
+        }
+    }
+
+    fn permanent(reason: String) -> Self {
+        Self {
+            reason,
+            transient: false,
+        }
+    }
+}
+
+/// Network errors and server hiccups, as opposed to missing refs or authentication failures,
+/// which git may follow with a hang up of the connection
+fn is_transient_git_error(stderr: &str) -> bool {
+    const PERMANENT: [&str; 6] = [
+        "not found",
+        "not our ref",
+        "authentication failed",
+        "could not read username",
+        "permission denied",
+        "returned error: 4",
+    ];
+    const TRANSIENT: [&str; 14] = [
+        "could not resolve host",
+        "temporary failure in name resolution",
+        "connection timed out",
+        "operation timed out",
+        "connection reset",
+        "connection refused",
+        "network is unreachable",
+        "failed to connect",
+        "the remote end hung up unexpectedly",
+        "early eof",
+        "rpc failed",
+        "unexpected disconnect",
+        "tls connection was non-properly terminated",
+        "returned error: 5",
+    ];
+
+    let stderr = stderr.to_lowercase();
+    !PERMANENT.iter().any(|pattern| stderr.contains(pattern))
+        && TRANSIENT.iter().any(|pattern| stderr.contains(pattern))
+}
+
+fn retry_delay(attempt: u32) -> Duration {
+    Duration::from_secs(2_u64.pow(attempt))
+}
+
+/// Clone the repositories that aren't cloned yet, a few at a time, then print a summary.
+/// A repository that fails to clone doesn't stop the others, but setup can't
+/// continue without the core repositories.
+pub fn clone_all(repos: &[Repository], config: &Config) -> std::io::Result<()> {
+    let progress = multi_progress("Cloning repositories...");
+    let bars: Vec<_> = repos
+        .iter()
+        .map(|repo| {
+            let bar = progress.add(spinner());
+            bar.start(format!("{}: waiting", repo.full_name()));
+            bar
+        })
+        .collect();
+
+    let results = parallel_map(repos, |index, repo| {
+        let bar = &bars[index];
+        let status = repo.clone_into_place(config, |message| {
+            bar.set_message(format!("{}: {message}", repo.full_name()));
+        });
+
+        match &status {
+            CloneStatus::Cloned | CloneStatus::Recloned(_) => {
+                bar.stop(format!("✓ Cloned {}", repo.full_name()));
+            }
+            CloneStatus::Skipped => {
+                bar.stop(format!("✓ Already cloned {}", repo.full_name()));
+            }
+            CloneStatus::Failed(_) => {
+                bar.error(format!("✗ Failed to clone {}", repo.full_name()));
+            }
+        }
+
+        status
+    });
+
+    let failed = results
+        .iter()
+        .filter(|status| matches!(status, CloneStatus::Failed(_)))
+        .count();
+
+    if failed > 0 {
+        progress.error(format!(
+            "Failed to clone {failed} of {} repositories",
+            repos.len()
+        ));
+    } else {
+        progress.stop();
+    }
+
+    note("Clone summary", clone_summary(repos, &results))?;
+
+    let failed_core: Vec<String> = repos
+        .iter()
+        .zip(&results)
+        .filter(|(repo, status)| {
+            matches!(status, CloneStatus::Failed(_)) && Repository::core().contains(repo)
+        })
+        .map(|(repo, _)| repo.full_name())
+        .collect();
+
+    if !failed_core.is_empty() {
+        return Err(Error::other(format!(
+            "Failed to clone {}, which is required",
+            failed_core.join(", ")
+        )));
+    }
+
+    if failed > 0 {
+        warning(
+            "Some optional repositories could not be cloned. Run the setup again to retry them.",
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Run a git command inside `dir` and return its stdout
+fn git_in(dir: &Path, args: &[&str]) -> std::io::Result<String> {
+    let output = Command::new("git").current_dir(dir).args(args).output()?;
+
+    if !output.status.success() {
+        return Err(Error::other(format!(
+            "`git {}` failed in {}: {}",
+            args.join(" "),
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn is_commit_sha(git_ref: &str) -> bool {
+    git_ref.len() == 40 && git_ref.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Run `job` for every item on a small pool of threads, returning the results in order
+fn parallel_map<T: Sync, R: Send>(items: &[T], job: impl Fn(usize, &T) -> R + Sync) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(items.iter().map(|_| None).collect::<Vec<_>>());
+
+    std::thread::scope(|scope| {
+        for _ in 0..MAX_PARALLEL_JOBS.min(items.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+
+                let result = job(index, item);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every item is processed"))
+        .collect()
+}
+
+/// The most useful line of git's stderr, usually the first `fatal:` one
+fn git_error_summary(stderr: &str) -> String {
+    stderr
+        .lines()
+        .find_map(|line| line.split_once("fatal:").map(|(_, message)| message))
+        .or_else(|| stderr.lines().rev().find(|line| !line.trim().is_empty()))
+        .unwrap_or("unknown error")
+        .trim()
+        .to_string()
+}
+
+/// Rows of cells with every column padded to its widest cell, two spaces apart
+fn aligned<const N: usize>(rows: &[[String; N]]) -> String {
+    let mut widths = [0; N];
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn clone_summary(repos: &[Repository], results: &[CloneStatus]) -> String {
+    let count = |expected: fn(&CloneStatus) -> bool| results.iter().filter(|s| expected(s)).count();
+
+    let rows: Vec<[String; 2]> = repos
+        .iter()
+        .zip(results)
+        .map(|(repo, status)| {
+            let status = match status {
+                CloneStatus::Cloned => "cloned".to_string(),
+                CloneStatus::Recloned(moved_to) => format!(
+                    "re-cloned (broken checkout moved to {})",
+                    moved_to.display()
+                ),
+                CloneStatus::Skipped => "already cloned".to_string(),
+                CloneStatus::Failed(reason) => format!("failed: {reason}"),
+            };
+            [repo.full_name(), status]
+        })
+        .collect();
+
+    format!(
+        "{}\n\n{} cloned, {} skipped, {} failed",
+        aligned(&rows),
+        count(|s| matches!(s, CloneStatus::Cloned | CloneStatus::Recloned(_))),
+        count(|s| *s == CloneStatus::Skipped),
+        count(|s| matches!(s, CloneStatus::Failed(_))),
+    )
+}
+
+pub fn run(args: &[String], config: Config) -> std::io::Result<()> {
+    match args.first().map(String::as_str) {
+        Some("add-fork") => add_fork(&args[1..], config),
+        Some("unshallow") => unshallow(&args[1..]),
+        Some("status") => status::run(&args[1..], false),
+        Some("pull") => status::run(&args[1..], true),
+        Some("sync") => sync::run(&args[1..]),
+        Some("checkout") => checkout::run(&args[1..], &config),
+        Some("lock") => lock::run(&args[1..]),
+        Some("branch") => branch::run(&args[1..]),
+        Some("mirror") => mirror::run(&args[1..], &config),
+        Some("worktree") => worktree::run(&args[1..], config),
+        Some("doctor") => doctor::run(&args[1..], &config),
+        Some("check") => check::run(&args[1..], &config),
+        Some("hooks") => hooks::run(&args[1..], &config),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Usage: repos <add-fork|unshallow|status|pull|sync|checkout|lock|branch|mirror|worktree|doctor|hooks|check> [repo...]",
+        )),
+    }
+}
+
+/// Resolve repository names given on the command line, defaulting to every cloned repository
+fn selected_repos(names: &[String]) -> std::io::Result<Vec<Repository>> {
+    if names.is_empty() {
+        return Ok(Repository::all()?
+            .into_iter()
+            .filter(Repository::is_cloned)
+            .collect());
+    }
+
+    names.iter().map(|name| Repository::find(name)).collect()
+}
+
+fn add_fork(names: &[String], mut config: Config) -> std::io::Result<()> {
+    intro("Adding fork remotes...")?;
+
+    if config.github_username.is_none() && config.forks.is_none() {
+        config.github_username = Some(prompt_for_github_username()?);
+        config.save()?;
+    }
+
+    for repo in selected_repos(names)? {
+        if !repo.is_cloned() {
+            warning(format!("{} is not cloned, skipping", repo.full_name()))?;
+            continue;
+        }
+
+        match repo.add_fork_remote(&config)? {
+            Some(url) => step(format!("✓ {}: origin → {url}", repo.full_name()))?,
+            None => info(format!("{}: no fork configured", repo.full_name()))?,
+        }
+    }
+
+    outro("Pushes now go to your forks, `upstream` still points at the original repositories")
+}
+
+fn unshallow(names: &[String]) -> std::io::Result<()> {
+    intro("Fetching full history...")?;
+
+    for repo in selected_repos(names)? {
+        if !repo.is_cloned() {
+            warning(format!("{} is not cloned, skipping", repo.full_name()))?;
+            continue;
+        }
+
+        let progress = spinner();
+        progress.start(format!("Unshallowing {}...", repo.full_name()));
+
+        match repo.unshallow() {
+            Ok(()) => progress.stop(format!("✓ Full history of {}", repo.full_name())),
+            Err(err) => progress.error(format!("✗ {}: {err}", repo.full_name())),
+        }
+    }
+
+    outro("Done")
+}
+
+pub fn prompt_for_github_username() -> std::io::Result<String> {
+    input("Your GitHub username, to add your forks as the `origin` remote (blank to skip)")
+        .required(false)
+        .interact()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_repository() {
+        let repo = Repository::new("lichess-org", "lila");
+        assert_eq!(repo.org, "lichess-org");
+        assert_eq!(repo.project, "lila");
+        assert_eq!(repo.full_name(), "lichess-org/lila");
+        assert_eq!(repo.url(), "https://github.com/lichess-org/lila");
+        assert_eq!(repo.clone_path(), Path::new("repos/lila"));
+    }
+
+    #[test]
+    fn test_fork_url() {
+        let lila = Repository::new("lichess-org", "lila");
+        let scalachess = Repository::new("lichess-org", "scalachess");
+
+        assert_eq!(lila.fork_url(&Config::default()), None);
+
+        let config = Config {
+            github_username: Some("octocat".to_string()),
+            forks: Some(BTreeMap::from([(
+                "scalachess".to_string(),
+                "git@github.com:octocat/my-scalachess.git".to_string(),
+            )])),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            lila.fork_url(&config),
+            Some("https://github.com/octocat/lila".to_string())
+        );
+        assert_eq!(
+            scalachess.fork_url(&config),
+            Some("git@github.com:octocat/my-scalachess.git".to_string())
+        );
+
+        let blank_username = Config {
+            github_username: Some(String::new()),
+            ..Default::default()
+        };
+        assert_eq!(lila.fork_url(&blank_username), None);
+    }
+
+    #[test]
+    fn test_clone_summary() {
+        let repos = [
+            Repository::new("lichess-org", "lila"),
+            Repository::new("lichess-org", "lila-ws"),
+            Repository::new("cyanfish", "bbpPairings"),
+        ];
+        let results = [
+            CloneStatus::Recloned(PathBuf::from("repos/lila.broken-1700000000")),
+            CloneStatus::Skipped,
+            CloneStatus::Failed("timeout".to_string()),
+        ];
+
+        assert_eq!(
+            clone_summary(&repos, &results),
+            [
+                "lichess-org/lila      re-cloned (broken checkout moved to repos/lila.broken-1700000000)",
+                "lichess-org/lila-ws   already cloned",
+                "cyanfish/bbpPairings  failed: timeout",
+                "",
+                "1 cloned, 1 skipped, 1 failed",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_clone_depth() {
+        let lila = Repository::new("lichess-org", "lila");
+        let scalachess = Repository::new("lichess-org", "scalachess");
+
+        assert_eq!(lila.clone_depth(&Config::default()), Some(1));
+
+        let config = Config {
+            clone_depth: Some(50),
+            clone_depths: Some(BTreeMap::from([("lila".to_string(), 0)])),
+            ..Default::default()
+        };
+        assert_eq!(lila.clone_depth(&config), None);
+        assert_eq!(scalachess.clone_depth(&config), Some(50));
+
+        let full_history = Config {
+            clone_depth: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(scalachess.clone_depth(&full_history), None);
+    }
+
+    #[test]
+    fn test_pinned_ref() {
+        let lila = Repository::new("lichess-org", "lila");
+        let config = Config {
+            refs: Some(BTreeMap::from([(
+                "lila".to_string(),
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4".to_string(),
+            )])),
+            ..Default::default()
+        };
+
+        assert_eq!(lila.pinned_ref(&Config::default()), None);
+        assert_eq!(
+            lila.pinned_ref(&config).as_deref(),
+            Some("e3b0c44298fc1c149afbf4c8996fb92427ae41e4")
+        );
+        assert_eq!(
+            Repository::new("lichess-org", "lila-ws").pinned_ref(&config),
+            None
+        );
+
+        assert!(is_commit_sha("e3b0c44298fc1c149afbf4c8996fb92427ae41e4"));
+        assert!(!is_commit_sha("e3b0c44"));
+        assert!(!is_commit_sha("master"));
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), Duration::from_secs(2));
+        assert_eq!(retry_delay(2), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_checkout_state() {
+        let path =
+            std::env::temp_dir().join(format!("lila-docker-checkout-{}", std::process::id()));
+        let repo = Repository::from_url("https://github.com/lichess-org/lila", Some(path.clone()));
+        let git = |args: &[&str]| {
+            assert!(Command::new("git")
+                .current_dir(&path)
+                .args(args)
+                .output()
+                .unwrap()
+                .status
+                .success());
+        };
+
+        std::fs::create_dir_all(&path).unwrap();
+        assert_eq!(repo.checkout_state(), CheckoutState::Empty);
+
+        std::fs::write(path.join("README.md"), "leftover").unwrap();
+        assert_eq!(repo.checkout_state(), CheckoutState::Broken);
+
+        git(&["init", "--quiet"]);
+        assert_eq!(repo.checkout_state(), CheckoutState::Broken);
+
+        git(&[
+            "-c",
+            "user.name=test",
+            "-c",
+            "user.email=test@example.com",
+            "commit",
+            "--quiet",
+            "--allow-empty",
+            "--message",
+            "init",
+        ]);
+        assert_eq!(repo.checkout_state(), CheckoutState::Valid);
+
+        repo.remove_partial_clone().unwrap();
+        assert_eq!(repo.checkout_state(), CheckoutState::Empty);
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parallel_map_keeps_order() {
+        let items: Vec<u64> = (0..20).collect();
+        let doubled = parallel_map(&items, |index, item| {
+            std::thread::sleep(Duration::from_millis(20 - item));
+            (index, item * 2)
+        });
+        assert_eq!(
+            doubled,
+            items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| (index, item * 2))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_is_transient_git_error() {
+        assert!(is_transient_git_error(
+            "Cloning into 'repos/lila'...\nfatal: unable to access 'https://github.com/lichess-org/lila.git/': Could not resolve host: github.com\n"
+        ));
+        assert!(is_transient_git_error(
+            "error: RPC failed; curl 92 HTTP/2 stream 5 was not closed cleanly: CANCEL (err 8)\nfatal: early EOF\n"
+        ));
+        assert!(is_transient_git_error(
+            "fatal: unable to access 'https://github.com/lichess-org/lila.git/': The requested URL returned error: 502\n"
+        ));
+        assert!(!is_transient_git_error(
+            "fatal: Remote branch no-such-branch not found in upstream upstream\n"
+        ));
+        assert!(!is_transient_git_error(
+            "fatal: Authentication failed for 'https://github.com/lichess-org/private.git/'\n"
+        ));
+        assert!(!is_transient_git_error(
+            "fatal: remote error: upload-pack: not our ref 0123456789abcdef0123456789abcdef01234567\nfatal: the remote end hung up unexpectedly\n"
+        ));
+    }
+
+    #[test]
+    fn test_aligned() {
+        let rows = [
+            ["lichess-org/lila".to_string(), "✓ rebased".to_string()],
+            [String::new(), String::new()],
+            ["Total".to_string(), "3 files".to_string()],
+        ];
+        assert_eq!(
+            aligned(&rows),
+            "lichess-org/lila  ✓ rebased\n\nTotal             3 files"
+        );
+    }
+
+    #[test]
+    fn test_git_error_summary() {
+        assert_eq!(
+            git_error_summary(
+                "Cloning into 'repos/lila'...\nfatal: unable to access 'https://github.com/lichess-org/lila/': Could not resolve host: github.com\n"
+            ),
+            "unable to access 'https://github.com/lichess-org/lila/': Could not resolve host: github.com"
+        );
+        assert_eq!(
+            git_error_summary("error: something\n\n"),
+            "error: something"
+        );
+        assert_eq!(git_error_summary(""), "unknown error");
+    }
+
+    #[test]
+    fn test_all_repositories_are_unique() {
+        let repos = Repository::all().unwrap();
+        let mut paths: Vec<PathBuf> = repos.iter().map(Repository::clone_path).collect();
+        paths.sort();
+        paths.dedup();
+        assert_eq!(paths.len(), repos.len());
+    }
+}
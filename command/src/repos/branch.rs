@@ -0,0 +1,361 @@
+use super::{aligned, git_error_summary, Repository};
+use cliclack::{intro, log::info, multiselect, note, outro};
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+    ops::Add,
+};
+
+const USAGE: &str =
+    "Usage: branch <create|switch|delete> <name> [repo...] [--force] | branch diff [repo...]";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Action {
+    Create,
+    Switch,
+    Delete { force: bool },
+}
+
+/// Totals of `git diff --shortstat`
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+struct DiffStat {
+    files: u32,
+    insertions: u32,
+    deletions: u32,
+}
+
+impl Add for DiffStat {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            files: self.files + other.files,
+            insertions: self.insertions + other.insertions,
+            deletions: self.deletions + other.deletions,
+        }
+    }
+}
+
+impl fmt::Display for DiffStat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if *self == Self::default() {
+            return write!(f, "no changes");
+        }
+
+        write!(
+            f,
+            "{} files, +{} -{}",
+            self.files, self.insertions, self.deletions
+        )
+    }
+}
+
+/// ` 3 files changed, 20 insertions(+), 4 deletions(-)`, where any part may be missing
+fn parse_shortstat(output: &str) -> DiffStat {
+    output
+        .split(',')
+        .filter_map(|part| {
+            let (count, label) = part.trim().split_once(' ')?;
+            Some((count.parse::<u32>().ok()?, label))
+        })
+        .fold(DiffStat::default(), |mut stat, (count, label)| {
+            if label.starts_with("file") {
+                stat.files = count;
+            } else if label.starts_with("insertion") {
+                stat.insertions = count;
+            } else if label.starts_with("deletion") {
+                stat.deletions = count;
+            }
+            stat
+        })
+}
+
+impl Repository {
+    pub fn has_local_branch(&self, branch: &str) -> bool {
+        self.git(&[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("refs/heads/{branch}"),
+        ])
+        .is_ok()
+    }
+
+    pub fn has_remote_branch(&self, branch: &str) -> bool {
+        self.git(&[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("refs/remotes/{}/{branch}", self.main_remote()),
+        ])
+        .is_ok()
+    }
+
+    /// Apply the action to one checkout, returning what was done or why it was not
+    fn apply_branch_action(&self, action: Action, branch: &str) -> Result<String, String> {
+        let current = self.current_branch();
+        let dirty = self.changed_files().map_err(|err| err.to_string())? > 0;
+
+        let git = |args: &[&str]| {
+            self.git(args)
+                .map_err(|err| git_error_summary(&err.to_string()))
+        };
+
+        match action {
+            Action::Create => {
+                if self.has_local_branch(branch) {
+                    return Err("branch already exists".to_string());
+                }
+                git(&["switch", "--quiet", "--create", branch])?;
+                Ok(format!(
+                    "created from {}",
+                    current.as_deref().unwrap_or("detached HEAD")
+                ))
+            }
+            Action::Switch => {
+                if current.as_deref() == Some(branch) {
+                    return Ok("already on it".to_string());
+                }
+                if dirty {
+                    return Err("uncommitted changes".to_string());
+                }
+                if self.has_local_branch(branch) {
+                    git(&["switch", "--quiet", branch])?;
+                    Ok("switched".to_string())
+                } else if self.has_remote_branch(branch) {
+                    let remote_branch = format!("{}/{branch}", self.main_remote());
+                    git(&[
+                        "switch",
+                        "--quiet",
+                        "--create",
+                        branch,
+                        "--track",
+                        &remote_branch,
+                    ])?;
+                    Ok(format!("switched, tracking {remote_branch}"))
+                } else {
+                    Err("no such branch".to_string())
+                }
+            }
+            Action::Delete { force } => {
+                if !self.has_local_branch(branch) {
+                    return Err("no such branch".to_string());
+                }
+                if current.as_deref() == Some(branch) {
+                    return Err("currently checked out, switch away first".to_string());
+                }
+                self.git(&["branch", if force { "-D" } else { "-d" }, branch])
+                    .map_err(|err| {
+                        if err.to_string().contains("not fully merged") {
+                            "not fully merged, use --force to delete anyway".to_string()
+                        } else {
+                            git_error_summary(&err.to_string())
+                        }
+                    })?;
+                Ok("deleted".to_string())
+            }
+        }
+    }
+
+    /// Committed changes since the branch left the remote default branch, uncommitted ones,
+    /// and both together, where a file changed by both is counted once
+    fn diff_stats(&self) -> Option<(DiffStat, DiffStat, DiffStat)> {
+        let base = format!("{}/{}", self.main_remote(), self.default_branch()?);
+        let merge_base = self.git(&["merge-base", &base, "HEAD"]).ok()?;
+        let committed = self
+            .git(&["diff", "--shortstat", &format!("{merge_base}..HEAD")])
+            .ok()?;
+        let uncommitted = self.git(&["diff", "--shortstat", "HEAD"]).ok()?;
+        let combined = self.git(&["diff", "--shortstat", &merge_base]).ok()?;
+
+        Some((
+            parse_shortstat(&committed),
+            parse_shortstat(&uncommitted),
+            parse_shortstat(&combined),
+        ))
+    }
+}
+
+fn prompt_for_repos(message: &str) -> std::io::Result<Vec<Repository>> {
+    let cloned: Vec<Repository> = Repository::all()?
+        .into_iter()
+        .filter(Repository::is_cloned)
+        .collect();
+
+    cloned
+        .into_iter()
+        .fold(multiselect(message), |prompt, repo| {
+            let label = repo.full_name();
+            let hint = repo.current_branch().unwrap_or_default();
+            prompt.item(repo, label, hint)
+        })
+        .interact()
+}
+
+fn diff_summary(repos: &[Repository]) -> String {
+    let mut total = DiffStat::default();
+
+    let mut rows: Vec<[String; 2]> = repos
+        .iter()
+        .map(|repo| {
+            let branch = repo.current_branch().unwrap_or("(detached)".to_string());
+            let summary = match repo.diff_stats() {
+                Some((committed, uncommitted, combined)) => {
+                    total = total + combined;
+                    format!("{branch}: {committed} committed, {uncommitted} uncommitted")
+                }
+                None => format!("{branch}: no common history with the default branch"),
+            };
+            [repo.full_name(), summary]
+        })
+        .collect();
+
+    rows.push([String::new(), String::new()]);
+    rows.push(["Total".to_string(), total.to_string()]);
+
+    aligned(&rows)
+}
+
+fn parse_action(args: &[String]) -> std::io::Result<(Action, String, Vec<String>)> {
+    let usage = || Error::new(ErrorKind::InvalidInput, USAGE);
+    let force = args.iter().any(|arg| arg == "--force");
+    let mut args = args.iter().filter(|arg| *arg != "--force");
+
+    let action = match args.next().map(String::as_str) {
+        Some("create") => Action::Create,
+        Some("switch") => Action::Switch,
+        Some("delete") => Action::Delete { force },
+        _ => return Err(usage()),
+    };
+    let branch = args.next().ok_or_else(usage)?.clone();
+
+    Ok((action, branch, args.cloned().collect()))
+}
+
+/// `branch <create|switch|delete> <name> [repo...]` and `branch diff [repo...]`
+pub fn run(args: &[String]) -> std::io::Result<()> {
+    if args.first().map(String::as_str) == Some("diff") {
+        intro("Changes across repositories")?;
+        let repos = super::selected_repos(&args[1..])?;
+        note("Diff against the default branches", diff_summary(&repos))?;
+        return outro("Done");
+    }
+
+    let (action, branch, names) = parse_action(args)?;
+
+    intro(match action {
+        Action::Create => format!("Creating branch {branch}"),
+        Action::Switch => format!("Switching to branch {branch}"),
+        Action::Delete { .. } => format!("Deleting branch {branch}"),
+    })?;
+
+    let repos = if !names.is_empty() {
+        names
+            .iter()
+            .map(|name| Repository::find(name))
+            .collect::<std::io::Result<Vec<_>>>()?
+    } else if action == Action::Create {
+        prompt_for_repos("Select the repositories to create the branch in:")?
+    } else {
+        // Only the checkouts that know about the branch
+        Repository::all()?
+            .into_iter()
+            .filter(|repo| {
+                repo.is_cloned()
+                    && (repo.has_local_branch(&branch)
+                        || (action == Action::Switch && repo.has_remote_branch(&branch)))
+            })
+            .collect()
+    };
+
+    if repos.is_empty() {
+        info(format!("No repository has a branch named {branch}"))?;
+        return outro("Nothing to do");
+    }
+
+    let results: Vec<Result<String, String>> = repos
+        .iter()
+        .map(|repo| {
+            if repo.is_cloned() {
+                repo.apply_branch_action(action, &branch)
+            } else {
+                Err("not cloned".to_string())
+            }
+        })
+        .collect();
+
+    let failed = results.iter().filter(|result| result.is_err()).count();
+    let rows: Vec<[String; 2]> = repos
+        .iter()
+        .zip(&results)
+        .map(|(repo, result)| {
+            let status = match result {
+                Ok(done) => format!("✓ {done}"),
+                Err(reason) => format!("✗ {reason}"),
+            };
+            [repo.full_name(), status]
+        })
+        .collect();
+    note(format!("Branch {branch}"), aligned(&rows))?;
+
+    if !matches!(action, Action::Delete { .. }) {
+        note("Diff against the default branches", diff_summary(&repos))?;
+    }
+
+    if failed > 0 {
+        outro(format!(
+            "{failed} of {} repositories could not be updated",
+            repos.len()
+        ))
+    } else {
+        outro("Done")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_shortstat() {
+        assert_eq!(
+            parse_shortstat(" 3 files changed, 20 insertions(+), 4 deletions(-)"),
+            DiffStat {
+                files: 3,
+                insertions: 20,
+                deletions: 4
+            }
+        );
+        assert_eq!(
+            parse_shortstat(" 1 file changed, 1 deletion(-)"),
+            DiffStat {
+                files: 1,
+                insertions: 0,
+                deletions: 1
+            }
+        );
+        assert_eq!(parse_shortstat("").to_string(), "no changes");
+        assert_eq!(
+            (parse_shortstat(" 1 file changed, 2 insertions(+)")
+                + parse_shortstat(" 2 files changed, 1 deletion(-)"))
+            .to_string(),
+            "3 files, +2 -1"
+        );
+    }
+
+    #[test]
+    fn test_parse_action() {
+        let args = ["delete", "feature", "lila", "--force", "lila-ws"].map(ToString::to_string);
+        assert_eq!(
+            parse_action(&args).unwrap(),
+            (
+                Action::Delete { force: true },
+                "feature".to_string(),
+                vec!["lila".to_string(), "lila-ws".to_string()]
+            )
+        );
+
+        assert!(parse_action(&["create".to_string()]).is_err());
+        assert!(parse_action(&["rename".to_string(), "x".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,329 @@
+use super::{aligned, git_in, selected_repos, Repository};
+use crate::Config;
+use cliclack::{
+    confirm, input, intro,
+    log::{info, step, warning},
+    note, outro,
+};
+use std::{
+    fmt,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Written by `./lila-docker setup` with the user and group the containers run as
+const DOCKER_ENV: &str = ".env";
+
+#[derive(Debug, PartialEq)]
+enum Fix {
+    /// Prompt for the missing `user.name` and `user.email` and set them globally
+    SetIdentity(Vec<&'static str>),
+    Run(Vec<String>),
+}
+
+impl fmt::Display for Fix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::SetIdentity(keys) => write!(
+                f,
+                "{}",
+                keys.iter()
+                    .map(|key| format!("git config --global {key} <...>"))
+                    .collect::<Vec<_>>()
+                    .join(" && ")
+            ),
+            Self::Run(command) => write!(f, "{}", command.join(" ")),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Issue {
+    /// The repository, or `git` for the global configuration
+    subject: String,
+    problem: String,
+    fix: Fix,
+}
+
+/// `KEY=value` lines of a `.env` file
+fn parse_env_value(contents: &str, key: &str) -> Option<String> {
+    contents.lines().find_map(|line| {
+        let (name, value) = line.split_once('=')?;
+        (name.trim() == key).then(|| value.trim().to_string())
+    })
+}
+
+/// The user and group the containers run as, or `None` on Docker Desktop where they run
+/// as root and file ownership is mapped to the host user
+fn container_user() -> Option<(u32, u32)> {
+    let contents = std::fs::read_to_string(DOCKER_ENV).ok()?;
+    let user_id: u32 = parse_env_value(&contents, "USER_ID")?.parse().ok()?;
+    let group_id: u32 = parse_env_value(&contents, "GROUP_ID")?.parse().ok()?;
+    (user_id != 0).then_some((user_id, group_id))
+}
+
+fn identity_issue() -> Option<Issue> {
+    let missing: Vec<&'static str> = ["user.name", "user.email"]
+        .into_iter()
+        .filter(|key| git_in(Path::new("."), &["config", "--get", key]).is_err())
+        .collect();
+
+    (!missing.is_empty()).then(|| Issue {
+        subject: "git".to_string(),
+        problem: format!("{} not set, commits will fail", missing.join(" and ")),
+        fix: Fix::SetIdentity(missing),
+    })
+}
+
+/// Entries of the checkout, and the checkout itself, owned by another user than `user_id`
+fn foreign_entries(entries: &[(PathBuf, u32)], user_id: u32) -> Vec<&(PathBuf, u32)> {
+    entries.iter().filter(|(_, uid)| *uid != user_id).collect()
+}
+
+impl Repository {
+    /// Git refuses to work in a checkout owned by someone else unless it is a `safe.directory`
+    fn ownership_refused(&self) -> bool {
+        self.git(&["rev-parse", "--git-dir"])
+            .is_err_and(|err| err.to_string().contains("dubious ownership"))
+    }
+
+    /// The checkout and its top-level entries with their owner. Containers running as root
+    /// leave their files, like `node_modules` or `target`, at that level.
+    fn owners(&self) -> std::io::Result<Vec<(PathBuf, u32)>> {
+        let path = self.clone_path();
+        let mut owners = vec![(path.clone(), std::fs::metadata(&path)?.uid())];
+        for entry in std::fs::read_dir(&path)? {
+            let entry = entry?;
+            owners.push((entry.path(), entry.metadata()?.uid()));
+        }
+        Ok(owners)
+    }
+
+    fn issues(&self, container_user: Option<(u32, u32)>) -> std::io::Result<Vec<Issue>> {
+        let mut issues = vec![];
+        let path = std::fs::canonicalize(self.clone_path())?;
+
+        if self.ownership_refused() {
+            issues.push(Issue {
+                subject: self.full_name(),
+                problem: "owned by another user, git refuses to work in it".to_string(),
+                fix: Fix::Run(
+                    [
+                        "git",
+                        "config",
+                        "--global",
+                        "--add",
+                        "safe.directory",
+                        &path.display().to_string(),
+                    ]
+                    .map(ToString::to_string)
+                    .to_vec(),
+                ),
+            });
+        }
+
+        if let Some((user_id, group_id)) = container_user {
+            let owners = self.owners()?;
+            let foreign = foreign_entries(&owners, user_id);
+            if let Some((example, uid)) = foreign.first() {
+                issues.push(Issue {
+                    subject: self.full_name(),
+                    problem: format!(
+                        "{} owned by uid {uid} (e.g. {}), the containers run as uid {user_id}",
+                        if foreign.len() == 1 {
+                            "1 entry".to_string()
+                        } else {
+                            format!("{} entries", foreign.len())
+                        },
+                        example.display()
+                    ),
+                    fix: Fix::Run(
+                        [
+                            "sudo",
+                            "chown",
+                            "-R",
+                            &format!("{user_id}:{group_id}"),
+                            &path.display().to_string(),
+                        ]
+                        .map(ToString::to_string)
+                        .to_vec(),
+                    ),
+                });
+            }
+        }
+
+        Ok(issues)
+    }
+}
+
+fn find_issues(repos: &[Repository]) -> std::io::Result<Vec<Issue>> {
+    let container_user = container_user();
+    let mut issues: Vec<Issue> = identity_issue().into_iter().collect();
+
+    for repo in repos.iter().filter(|repo| repo.is_cloned()) {
+        issues.extend(repo.issues(container_user)?);
+    }
+
+    Ok(issues)
+}
+
+fn issues_table(issues: &[Issue]) -> String {
+    aligned(
+        &issues
+            .iter()
+            .map(|issue| [issue.subject.clone(), issue.problem.clone()])
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn set_identity(keys: &[&str], config: &Config) -> std::io::Result<()> {
+    for key in keys {
+        let value: String = if *key == "user.email" {
+            let mut prompt = input("Your email for git commits:");
+            if let Some(username) = &config.github_username {
+                prompt = prompt.default_input(&format!("{username}@users.noreply.github.com"));
+            }
+            prompt.interact()?
+        } else {
+            input("Your name for git commits:").interact()?
+        };
+        git_in(Path::new("."), &["config", "--global", key, &value])?;
+    }
+    Ok(())
+}
+
+fn apply(fix: &Fix, config: &Config) -> std::io::Result<()> {
+    match fix {
+        Fix::SetIdentity(keys) => set_identity(keys, config),
+        Fix::Run(command) => {
+            let status = Command::new(&command[0]).args(&command[1..]).status()?;
+            if status.success() {
+                Ok(())
+            } else {
+                Err(std::io::Error::other(format!("`{fix}` failed")))
+            }
+        }
+    }
+}
+
+/// Report the issues and offer to fix them, or fix them all without asking
+fn offer_fixes(issues: &[Issue], fix_all: bool, config: &Config) -> std::io::Result<usize> {
+    let mut fixed = 0;
+
+    for issue in issues {
+        if !fix_all
+            && !confirm(format!(
+                "{}: {}. Fix it with `{}`?",
+                issue.subject, issue.problem, issue.fix
+            ))
+            .interact()?
+        {
+            continue;
+        }
+
+        match apply(&issue.fix, config) {
+            Ok(()) => {
+                step(format!("✓ {}: fixed", issue.subject))?;
+                fixed += 1;
+            }
+            Err(err) => warning(format!("✗ {}: {err}", issue.subject))?,
+        }
+    }
+
+    Ok(fixed)
+}
+
+/// Run at the end of `setup`: offers the fixes, or only reports the issues when noninteractive
+pub fn preflight(repos: &[Repository], config: &Config, interactive: bool) -> std::io::Result<()> {
+    let issues = find_issues(repos)?;
+
+    if issues.is_empty() {
+        return step("✓ Git identity and repository ownership");
+    }
+
+    if !interactive {
+        return warning(format!(
+            "{}\nFix them with `./lila-docker repos check`",
+            issues_table(&issues)
+        ));
+    }
+
+    note("Git setup issues", issues_table(&issues))?;
+    offer_fixes(&issues, false, config)?;
+    Ok(())
+}
+
+/// `repos check [--fix] [repo...]`
+pub fn run(args: &[String], config: &Config) -> std::io::Result<()> {
+    let fix_all = args.iter().any(|arg| arg == "--fix");
+    let names: Vec<String> = args.iter().filter(|arg| *arg != "--fix").cloned().collect();
+
+    intro("Checking git identity and repository ownership...")?;
+
+    if container_user().is_none() {
+        info("Containers run as root or on Docker Desktop, file ownership is not checked")?;
+    }
+
+    let issues = find_issues(&selected_repos(&names)?)?;
+    if issues.is_empty() {
+        return outro("✓ Git is ready to commit in every repository");
+    }
+
+    note("Git setup issues", issues_table(&issues))?;
+    let fixed = offer_fixes(&issues, fix_all, config)?;
+
+    outro(format!("Fixed {fixed} of {} issues", issues.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_env_value() {
+        let contents = "USER_ID=1000\nGROUP_ID=1001\n";
+        assert_eq!(
+            parse_env_value(contents, "USER_ID").as_deref(),
+            Some("1000")
+        );
+        assert_eq!(
+            parse_env_value(contents, "GROUP_ID").as_deref(),
+            Some("1001")
+        );
+        assert_eq!(parse_env_value(contents, "LILA_DOMAIN"), None);
+    }
+
+    #[test]
+    fn test_foreign_entries() {
+        let entries = vec![
+            (PathBuf::from("repos/lila"), 1000),
+            (PathBuf::from("repos/lila/.git"), 1000),
+            (PathBuf::from("repos/lila/node_modules"), 0),
+        ];
+        assert_eq!(
+            foreign_entries(&entries, 1000),
+            vec![&(PathBuf::from("repos/lila/node_modules"), 0)]
+        );
+        assert!(foreign_entries(&entries[..2], 1000).is_empty());
+    }
+
+    #[test]
+    fn test_fix_display() {
+        assert_eq!(
+            Fix::SetIdentity(vec!["user.name", "user.email"]).to_string(),
+            "git config --global user.name <...> && git config --global user.email <...>"
+        );
+        assert_eq!(
+            Fix::Run(vec![
+                "sudo".to_string(),
+                "chown".to_string(),
+                "-R".to_string(),
+                "1000:1000".to_string(),
+                "/home/me/lila-docker/repos/lila".to_string(),
+            ])
+            .to_string(),
+            "sudo chown -R 1000:1000 /home/me/lila-docker/repos/lila"
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use super::Repository;
+use crate::{compose, Config};
+use cliclack::{intro, outro, spinner};
+use std::io::{Error, ErrorKind};
+
+/// The PR branch needs some history to be merged into, when the repository is shallow
+const PR_FETCH_MIN_DEPTH: u32 = 25;
+
+impl Repository {
+    pub fn pull_request_url(&self, pr: u32) -> String {
+        format!("{}/pull/{pr}", self.url())
+    }
+
+    /// Fetch a pull request into a `pr-<n>` branch and check it out, along with its submodules.
+    /// Refuses to run over uncommitted changes, or to reset a `pr-<n>` branch with local commits.
+    pub fn checkout_pr(&self, pr: u32, config: &Config) -> std::io::Result<String> {
+        if !self.is_cloned() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("{} is not cloned", self.full_name()),
+            ));
+        }
+
+        if self.changed_files()? > 0 {
+            return Err(Error::other(format!(
+                "{} has uncommitted changes, commit or stash them first",
+                self.full_name()
+            )));
+        }
+
+        let branch = format!("pr-{pr}");
+        let pr_ref = format!("pull/{pr}/head");
+        // A full history stays full, `--depth` would make the repository shallow again
+        let depth = self.is_shallow()?.then(|| {
+            self.clone_depth(config)
+                .unwrap_or_default()
+                .max(PR_FETCH_MIN_DEPTH)
+                .to_string()
+        });
+
+        let mut fetch = vec!["fetch", "--quiet", self.main_remote(), &pr_ref];
+        if let Some(depth) = &depth {
+            fetch.extend(["--depth", depth]);
+        }
+        self.git(&fetch)?;
+
+        let has_branch = self
+            .git(&[
+                "rev-parse",
+                "--verify",
+                "--quiet",
+                &format!("refs/heads/{branch}"),
+            ])
+            .is_ok();
+        if has_branch
+            && self
+                .git(&["merge-base", "--is-ancestor", &branch, "FETCH_HEAD"])
+                .is_err()
+        {
+            return Err(Error::other(format!(
+                "{branch} in {} has commits that are not in the pull request, not overwriting it",
+                self.full_name()
+            )));
+        }
+
+        self.git(&["checkout", "--quiet", "-B", &branch, "FETCH_HEAD"])?;
+        self.git(&["submodule", "update", "--init", "--recursive"])?;
+
+        Ok(branch)
+    }
+}
+
+/// `lila#1234`, `lichess-org/lila#1234` or `https://github.com/lichess-org/lila/pull/1234`
+fn parse_pr_spec(spec: &str) -> std::io::Result<(String, u32)> {
+    let invalid = || {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("Expected <repo>#<pr> or a pull request URL, got `{spec}`"),
+        )
+    };
+
+    let (name, pr) = if let Some(parts) = spec.split_once('#') {
+        parts
+    } else {
+        spec.trim_end_matches('/')
+            .strip_prefix("https://github.com/")
+            .and_then(|path| path.split_once("/pull/"))
+            .ok_or_else(invalid)?
+    };
+
+    let pr = pr.parse::<u32>().map_err(|_| invalid())?;
+    if name.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok((name.to_string(), pr))
+}
+
+/// `checkout <repo>#<pr>`
+pub fn run(args: &[String], config: &Config) -> std::io::Result<()> {
+    let [spec] = args else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Usage: checkout <repo>#<pr>",
+        ));
+    };
+
+    let (name, pr) = parse_pr_spec(spec)?;
+    let repo = Repository::find(&name)?;
+
+    intro(format!("Checking out {}#{pr}", repo.full_name()))?;
+
+    let progress = spinner();
+    progress.start(format!("Fetching {}...", repo.pull_request_url(pr)));
+
+    match repo.checkout_pr(pr, config) {
+        Ok(branch) => progress.stop(format!(
+            "✓ Checked out {} on branch {branch}",
+            repo.pull_request_url(pr)
+        )),
+        Err(err) => {
+            progress.error(format!("✗ {err}"));
+            return Err(err);
+        }
+    }
+
+    compose::offer_restart(config, &repo.clone_path())?;
+
+    outro("Done")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pr_spec() {
+        assert_eq!(
+            parse_pr_spec("lila#1234").unwrap(),
+            ("lila".to_string(), 1234)
+        );
+        assert_eq!(
+            parse_pr_spec("lichess-org/scalachess#56").unwrap(),
+            ("lichess-org/scalachess".to_string(), 56)
+        );
+        assert_eq!(
+            parse_pr_spec("https://github.com/lichess-org/chessground/pull/789/").unwrap(),
+            ("lichess-org/chessground".to_string(), 789)
+        );
+
+        assert!(parse_pr_spec("lila").is_err());
+        assert!(parse_pr_spec("lila#abc").is_err());
+        assert!(parse_pr_spec("#12").is_err());
+        assert!(parse_pr_spec("https://github.com/lichess-org/lila/issues/12").is_err());
+    }
+}
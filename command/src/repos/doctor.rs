@@ -0,0 +1,285 @@
+use super::{
+    aligned, git_error_summary, selected_repos,
+    status::{Submodule, SubmoduleState},
+    Repository,
+};
+use crate::Config;
+use cliclack::{
+    confirm, intro,
+    log::{info, step, warning},
+    note, outro, spinner,
+};
+use std::collections::BTreeMap;
+
+/// A submodule that is not initialized, not at the recorded commit, or has local changes
+#[derive(Debug, PartialEq)]
+struct SubmoduleIssue {
+    path: String,
+    commit: String,
+    state: SubmoduleState,
+    changed_files: usize,
+}
+
+impl SubmoduleIssue {
+    fn describe(&self) -> String {
+        let state = match self.state {
+            SubmoduleState::UpToDate => None,
+            SubmoduleState::Uninitialized => Some("not initialized".to_string()),
+            SubmoduleState::WrongCommit => Some(format!(
+                "at {}, not the recorded commit",
+                &self.commit[..self.commit.len().min(7)]
+            )),
+            SubmoduleState::Conflicted => Some("merge conflict".to_string()),
+        };
+        let changes =
+            (self.changed_files > 0).then(|| format!("{} uncommitted changes", self.changed_files));
+
+        state
+            .into_iter()
+            .chain(changes)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// `git submodule update` would check out another commit
+    fn needs_update(&self) -> bool {
+        matches!(
+            self.state,
+            SubmoduleState::Uninitialized | SubmoduleState::WrongCommit
+        )
+    }
+}
+
+/// Keep the submodules that need attention
+fn diagnose(
+    submodules: Vec<Submodule>,
+    changed_files: &BTreeMap<String, usize>,
+) -> Vec<SubmoduleIssue> {
+    submodules
+        .into_iter()
+        .map(|submodule| SubmoduleIssue {
+            changed_files: changed_files
+                .get(&submodule.path)
+                .copied()
+                .unwrap_or_default(),
+            path: submodule.path,
+            commit: submodule.commit,
+            state: submodule.state,
+        })
+        .filter(|issue| issue.state != SubmoduleState::UpToDate || issue.changed_files > 0)
+        .collect()
+}
+
+/// `<path> <count>` lines printed by the `submodule foreach` in `submodule_changes`
+fn parse_changed_files(output: &str) -> BTreeMap<String, usize> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (path, count) = line.trim().rsplit_once(' ')?;
+            Some((path.to_string(), count.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// Why the submodules of a repository cannot be repaired automatically, if they cannot
+fn repair_blocker(issues: &[SubmoduleIssue]) -> Option<String> {
+    if let Some(issue) = issues
+        .iter()
+        .find(|issue| issue.state == SubmoduleState::Conflicted)
+    {
+        return Some(format!(
+            "resolve the merge conflict in {} first",
+            issue.path
+        ));
+    }
+
+    issues
+        .iter()
+        .find(|issue| issue.needs_update() && issue.changed_files > 0)
+        .map(|issue| format!("commit or stash the changes in {} first", issue.path))
+}
+
+impl Repository {
+    /// Number of uncommitted changes in each initialized submodule
+    fn submodule_changes(&self) -> std::io::Result<BTreeMap<String, usize>> {
+        Ok(parse_changed_files(&self.git(&[
+            "submodule",
+            "foreach",
+            "--recursive",
+            "--quiet",
+            r#"echo "$displaypath $(git status --porcelain | wc -l)""#,
+        ])?))
+    }
+
+    fn submodule_issues(&self) -> std::io::Result<Vec<SubmoduleIssue>> {
+        Ok(diagnose(self.submodule_list()?, &self.submodule_changes()?))
+    }
+
+    /// Point the submodules at the URLs of `.gitmodules` (unless they come from the mirror)
+    /// and check out the recorded commits
+    fn repair_submodules(&self, config: &Config) -> std::io::Result<()> {
+        if self.available_mirror(config).is_some() {
+            // Git refuses local submodule URLs unless explicitly allowed
+            self.git(&[
+                "-c",
+                "protocol.file.allow=always",
+                "submodule",
+                "update",
+                "--init",
+                "--recursive",
+            ])?;
+        } else {
+            self.git(&["submodule", "sync", "--recursive", "--quiet"])?;
+            self.git(&["submodule", "update", "--init", "--recursive"])?;
+        }
+        Ok(())
+    }
+}
+
+/// `repos doctor [--fix] [repo...]`
+pub fn run(args: &[String], config: &Config) -> std::io::Result<()> {
+    let fix = args.iter().any(|arg| arg == "--fix");
+    let names: Vec<String> = args.iter().filter(|arg| *arg != "--fix").cloned().collect();
+
+    intro("Checking submodules...")?;
+
+    let mut repairable = vec![];
+    let mut rows = vec![];
+
+    for repo in selected_repos(&names)?
+        .into_iter()
+        .filter(Repository::is_cloned)
+    {
+        let issues = match repo.submodule_issues() {
+            Ok(issues) => issues,
+            Err(err) => {
+                warning(format!("{}: {err}", repo.full_name()))?;
+                continue;
+            }
+        };
+
+        if issues.is_empty() {
+            continue;
+        }
+
+        for issue in &issues {
+            rows.push([repo.full_name(), issue.path.clone(), issue.describe()]);
+        }
+
+        match repair_blocker(&issues) {
+            Some(reason) => rows.push([repo.full_name(), String::new(), format!("→ {reason}")]),
+            None if issues.iter().any(SubmoduleIssue::needs_update) => repairable.push(repo),
+            None => {}
+        }
+    }
+
+    if rows.is_empty() {
+        return outro("✓ All submodules are initialized and at their recorded commits");
+    }
+
+    note("Submodule issues", aligned(&rows))?;
+
+    if repairable.is_empty() {
+        return outro("Nothing can be repaired automatically");
+    }
+
+    let names: Vec<String> = repairable.iter().map(Repository::full_name).collect();
+    if !fix
+        && !confirm(format!(
+            "Sync and update the submodules of {}?",
+            names.join(", ")
+        ))
+        .interact()?
+    {
+        info("Repair them later with `./lila-docker repos doctor --fix`")?;
+        return outro("Done");
+    }
+
+    for repo in repairable {
+        let progress = spinner();
+        progress.start(format!("Updating submodules of {}...", repo.full_name()));
+
+        match repo
+            .repair_submodules(config)
+            .and_then(|()| repo.submodule_issues())
+        {
+            Ok(issues) if issues.iter().any(SubmoduleIssue::needs_update) => progress.error(
+                format!("✗ {} still has submodules to update", repo.full_name()),
+            ),
+            Ok(_) => progress.stop(format!("✓ {}", repo.full_name())),
+            Err(err) => progress.error(format!(
+                "✗ {}: {}",
+                repo.full_name(),
+                git_error_summary(&err.to_string())
+            )),
+        }
+    }
+
+    step("Rebuild the UI if it failed because of a submodule: `./lila-docker ui`")?;
+    outro("Done")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn submodule(path: &str, state: SubmoduleState) -> Submodule {
+        Submodule {
+            path: path.to_string(),
+            commit: "9f8e7d6c5b4a3f2e1d0c9b8a7f6e5d4c3b2a1f0e".to_string(),
+            state,
+        }
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let changed_files = parse_changed_files("ui/@build/cssVars 0\npublic/piece-css 2\n");
+        assert_eq!(changed_files.get("public/piece-css"), Some(&2));
+
+        let issues = diagnose(
+            vec![
+                submodule("ui/@build/cssVars", SubmoduleState::UpToDate),
+                submodule("public/piece-css", SubmoduleState::UpToDate),
+                submodule("public/vendor/stockfish", SubmoduleState::Uninitialized),
+            ],
+            &changed_files,
+        );
+
+        assert_eq!(
+            issues
+                .iter()
+                .map(|issue| (issue.path.as_str(), issue.describe()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("public/piece-css", "2 uncommitted changes".to_string()),
+                ("public/vendor/stockfish", "not initialized".to_string()),
+            ]
+        );
+        assert_eq!(repair_blocker(&issues), None);
+    }
+
+    #[test]
+    fn test_repair_blocker() {
+        let dirty_wrong_commit = diagnose(
+            vec![submodule("public/piece-css", SubmoduleState::WrongCommit)],
+            &BTreeMap::from([("public/piece-css".to_string(), 1)]),
+        );
+        assert_eq!(
+            dirty_wrong_commit[0].describe(),
+            "at 9f8e7d6, not the recorded commit, 1 uncommitted changes"
+        );
+        assert_eq!(
+            repair_blocker(&dirty_wrong_commit).as_deref(),
+            Some("commit or stash the changes in public/piece-css first")
+        );
+
+        let conflicted = diagnose(
+            vec![submodule("public/piece-css", SubmoduleState::Conflicted)],
+            &BTreeMap::new(),
+        );
+        assert_eq!(
+            repair_blocker(&conflicted).as_deref(),
+            Some("resolve the merge conflict in public/piece-css first")
+        );
+    }
+}
@@ -0,0 +1,391 @@
+use super::{aligned, git_in, selected_repos, Repository};
+use crate::Config;
+use cliclack::{
+    intro,
+    log::{info, step},
+    note, outro,
+};
+use std::{
+    collections::BTreeMap,
+    io::{Error, ErrorKind},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const USAGE: &str = "Usage: hooks <list|enable|disable> [--pre-commit] [repo...] | hooks run <pre-commit|pre-push> <repo> <checkout>";
+
+const HOOKS: [&str; 2] = ["pre-commit", "pre-push"];
+
+/// Formatting a whole repository takes a while, so only pushes are checked unless
+/// `--pre-commit` is given
+const DEFAULT_HOOKS: [&str; 1] = ["pre-push"];
+
+/// Repositories that `./lila-docker format <repo>` knows how to format
+const FORMATTED_REPOS: [&str; 4] = ["lila", "lila-ws", "chessground", "pgn-viewer"];
+
+/// Second line of the hooks written by lila-docker, to tell them apart from the user's own
+const MARKER: &str = "# Installed by lila-docker";
+
+#[derive(Debug, PartialEq)]
+enum HookState {
+    Missing,
+    Installed,
+    Custom,
+}
+
+impl HookState {
+    fn of(script: Option<&str>) -> Self {
+        match script {
+            None => Self::Missing,
+            Some(script) if script.lines().any(|line| line.starts_with(MARKER)) => Self::Installed,
+            Some(_) => Self::Custom,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Self::Missing => "-",
+            Self::Installed => "✓ enabled",
+            Self::Custom => "your own hook",
+        }
+    }
+}
+
+/// The hook lets `hooks run` of the lila-docker checkout at `root` format the repository
+/// it is called from
+fn hook_script(hook: &str, project: &str, root: &Path) -> String {
+    let skip = hook.trim_start_matches("pre-");
+    format!(
+        r#"#!/bin/sh
+{MARKER}, manage with `./lila-docker hooks`
+# Skip it once with `git {skip} --no-verify`
+exec "{}/lila-docker" repos hooks run {hook} {project} "$(pwd)"
+"#,
+        root.display()
+    )
+}
+
+impl Repository {
+    fn has_formatter(&self) -> bool {
+        FORMATTED_REPOS.contains(&self.project.as_str())
+    }
+
+    /// `.git/hooks`, shared by the worktrees, or wherever `core.hooksPath` points
+    fn hooks_dir(&self) -> std::io::Result<PathBuf> {
+        let dir = PathBuf::from(self.git(&["rev-parse", "--git-path", "hooks"])?);
+        Ok(if dir.is_absolute() {
+            dir
+        } else {
+            self.clone_path().join(dir)
+        })
+    }
+
+    fn hook_states(&self) -> std::io::Result<Vec<HookState>> {
+        let dir = self.hooks_dir()?;
+        Ok(HOOKS
+            .iter()
+            .map(|hook| HookState::of(std::fs::read_to_string(dir.join(hook)).ok().as_deref()))
+            .collect())
+    }
+
+    /// Write the hooks, keeping any hook the user wrote. Returns the hooks that were kept.
+    fn enable_hooks(
+        &self,
+        hooks: &[&'static str],
+        root: &Path,
+    ) -> std::io::Result<Vec<&'static str>> {
+        let dir = self.hooks_dir()?;
+        std::fs::create_dir_all(&dir)?;
+        let mut kept = vec![];
+
+        for (hook, state) in HOOKS.into_iter().zip(self.hook_states()?) {
+            if !hooks.contains(&hook) {
+                continue;
+            }
+            if state == HookState::Custom {
+                kept.push(hook);
+                continue;
+            }
+            let path = dir.join(hook);
+            std::fs::write(&path, hook_script(hook, &self.project, root))?;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+        }
+
+        Ok(kept)
+    }
+
+    fn disable_hooks(&self) -> std::io::Result<()> {
+        let dir = self.hooks_dir()?;
+        for (hook, state) in HOOKS.into_iter().zip(self.hook_states()?) {
+            if state == HookState::Installed {
+                std::fs::remove_file(dir.join(hook))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn lila_docker_root() -> std::io::Result<PathBuf> {
+    std::fs::canonicalize(std::env::current_dir()?)
+}
+
+fn describe_kept(kept: &[&str]) -> String {
+    if kept.is_empty() {
+        "✓ enabled".to_string()
+    } else {
+        format!("✓ enabled, kept your own {}", kept.join(" and "))
+    }
+}
+
+/// Install the default hooks in the cloned repositories that have a formatter
+pub fn install(repos: &[Repository]) -> std::io::Result<()> {
+    install_hooks(repos, &DEFAULT_HOOKS)
+}
+
+fn install_hooks(repos: &[Repository], hooks: &[&'static str]) -> std::io::Result<()> {
+    let root = lila_docker_root()?;
+
+    for repo in repos
+        .iter()
+        .filter(|repo| repo.has_formatter() && repo.is_cloned())
+    {
+        let kept = repo.enable_hooks(hooks, &root)?;
+        step(format!("{}: {}", repo.full_name(), describe_kept(&kept)))?;
+    }
+
+    Ok(())
+}
+
+/// Files of the checkout with unstaged changes, and the hash of their content
+fn unstaged_files(checkout: &Path) -> std::io::Result<BTreeMap<String, String>> {
+    let paths = git_in(checkout, &["diff", "--name-only", "--diff-filter=d"])?;
+    let paths: Vec<&str> = paths.lines().collect();
+    if paths.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+
+    let hashes = git_in(
+        checkout,
+        &[&["hash-object", "--"], paths.as_slice()].concat(),
+    )?;
+
+    Ok(paths
+        .into_iter()
+        .map(ToString::to_string)
+        .zip(hashes.lines().map(ToString::to_string))
+        .collect())
+}
+
+/// Files the formatter changed that are part of what is being committed or pushed: the staged
+/// ones and the ones without unstaged edits before. Reformatted unstaged edits are left alone.
+fn unformatted_files(
+    before: &BTreeMap<String, String>,
+    after: &BTreeMap<String, String>,
+    staged: &[&str],
+) -> Vec<String> {
+    after
+        .iter()
+        .filter(|(path, hash)| before.get(*path) != Some(*hash))
+        .map(|(path, _)| path)
+        .chain(before.keys().filter(|path| !after.contains_key(*path)))
+        .filter(|path| staged.contains(&path.as_str()) || !before.contains_key(*path))
+        .cloned()
+        .collect()
+}
+
+/// Called by the hooks: run the formatter of the repository and fail if it changed any file
+/// being committed or pushed, unless the checkout is a worktree the containers do not mount
+fn check_formatting(
+    hook: &str,
+    repo: &Repository,
+    checkout: &Path,
+    config: &Config,
+) -> std::io::Result<()> {
+    let checkout = std::fs::canonicalize(checkout)?;
+    if std::fs::canonicalize(repo.mounted_checkout(config)).ok() != Some(checkout.clone()) {
+        return info(format!(
+            "{} is not mounted in the containers, skipping the formatting check",
+            checkout.display()
+        ));
+    }
+
+    let before = unstaged_files(&checkout)?;
+
+    if !Command::new("./lila-docker")
+        .args(["format", &repo.project])
+        .status()?
+        .success()
+    {
+        return Err(Error::other(format!(
+            "`./lila-docker format {}` failed, skip the check with `--no-verify`",
+            repo.project
+        )));
+    }
+
+    let staged = git_in(&checkout, &["diff", "--cached", "--name-only"])?;
+    let unformatted = unformatted_files(
+        &before,
+        &unstaged_files(&checkout)?,
+        &staged.lines().collect::<Vec<_>>(),
+    );
+    if !unformatted.is_empty() {
+        return Err(Error::other(format!(
+            "The formatter changed {} in {}, review and {} again",
+            unformatted.join(", "),
+            checkout.display(),
+            if hook == "pre-commit" {
+                "stage them before committing"
+            } else {
+                "commit them before pushing"
+            }
+        )));
+    }
+
+    Ok(())
+}
+
+fn list(repos: &[Repository]) -> std::io::Result<()> {
+    let mut rows = vec![[String::new(), HOOKS[0].to_string(), HOOKS[1].to_string()]];
+
+    for repo in repos {
+        let states = repo.hook_states()?;
+        rows.push([
+            repo.full_name(),
+            states[0].describe().to_string(),
+            states[1].describe().to_string(),
+        ]);
+    }
+
+    note("Git hooks", aligned(&rows))?;
+    outro("Enable them with `./lila-docker hooks enable [repo...]`")
+}
+
+/// `hooks <list|enable|disable> [repo...]` and `hooks run <hook> <repo> <checkout>`
+pub fn run(args: &[String], config: &Config) -> std::io::Result<()> {
+    let usage = || Error::new(ErrorKind::InvalidInput, USAGE);
+
+    if let [action, hook, project, checkout] = args {
+        if action == "run" && HOOKS.contains(&hook.as_str()) {
+            return check_formatting(
+                hook,
+                &Repository::find(project)?,
+                Path::new(checkout),
+                config,
+            );
+        }
+    }
+
+    let Some((action, args)) = args
+        .split_first()
+        .filter(|(action, _)| ["list", "enable", "disable"].contains(&action.as_str()))
+    else {
+        return Err(usage());
+    };
+    let pre_commit = args.iter().any(|arg| arg == "--pre-commit");
+    let names: Vec<String> = args
+        .iter()
+        .filter(|arg| *arg != "--pre-commit")
+        .cloned()
+        .collect();
+    let names = names.as_slice();
+
+    let repos: Vec<Repository> = if names.is_empty() {
+        selected_repos(names)?
+            .into_iter()
+            .filter(Repository::has_formatter)
+            .collect()
+    } else {
+        let repos = selected_repos(names)?;
+        if let Some(repo) = repos.iter().find(|repo| !repo.has_formatter()) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "`./lila-docker format` does not support {}, hooks are available for {}",
+                    repo.full_name(),
+                    FORMATTED_REPOS.join(", ")
+                ),
+            ));
+        }
+        repos.into_iter().filter(Repository::is_cloned).collect()
+    };
+
+    match action.as_str() {
+        "list" => {
+            intro("Git hooks")?;
+            list(&repos)
+        }
+        "enable" => {
+            intro("Enabling git hooks...")?;
+            if pre_commit {
+                install_hooks(&repos, &HOOKS)?;
+                outro(
+                    "Commits and pushes now check the formatting, skip it once with `--no-verify`",
+                )
+            } else {
+                install(&repos)?;
+                outro("Pushes now check the formatting, skip it once with `--no-verify`. Add `--pre-commit` to check commits too")
+            }
+        }
+        "disable" => {
+            intro("Disabling git hooks...")?;
+            for repo in &repos {
+                repo.disable_hooks()?;
+                step(format!("{}: disabled", repo.full_name()))?;
+            }
+            outro("Done")
+        }
+        _ => Err(usage()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_state() {
+        let script = hook_script("pre-push", "lila-ws", Path::new("/home/me/lila-docker"));
+        assert_eq!(HookState::of(Some(&script)), HookState::Installed);
+        assert_eq!(
+            HookState::of(Some("#!/bin/sh\nnpx lint-staged\n")),
+            HookState::Custom
+        );
+        assert_eq!(HookState::of(None), HookState::Missing);
+    }
+
+    #[test]
+    fn test_unformatted_files() {
+        let files = |files: &[(&str, &str)]| -> BTreeMap<String, String> {
+            files
+                .iter()
+                .map(|(path, hash)| ((*path).to_string(), (*hash).to_string()))
+                .collect()
+        };
+        let before = files(&[("staged.ts", "a"), ("wip.ts", "b"), ("untouched.ts", "c")]);
+        let after = files(&[
+            ("staged.ts", "a2"),
+            ("wip.ts", "b2"),
+            ("untouched.ts", "c"),
+            ("committed.scala", "d"),
+        ]);
+
+        assert_eq!(
+            unformatted_files(&before, &after, &["staged.ts"]),
+            vec!["committed.scala", "staged.ts"]
+        );
+        assert!(unformatted_files(&before, &before, &["staged.ts"]).is_empty());
+    }
+
+    #[test]
+    fn test_hook_script() {
+        assert_eq!(
+            hook_script("pre-commit", "lila", Path::new("/home/me/lila-docker")),
+            r#"#!/bin/sh
+# Installed by lila-docker, manage with `./lila-docker hooks`
+# Skip it once with `git commit --no-verify`
+exec "/home/me/lila-docker/lila-docker" repos hooks run pre-commit lila "$(pwd)"
+"#
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use super::{selected_repos, Repository};
+use crate::Config;
+use cliclack::{
+    intro,
+    log::{step, warning},
+    outro,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, io::Error, io::ErrorKind, path::Path};
+
+/// Exact commits of the checkouts, to reproduce a setup with `setup --locked`
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+pub struct Lockfile {
+    /// Commit SHA per repository name, like `refs` in the settings
+    repos: BTreeMap<String, String>,
+}
+
+impl Lockfile {
+    const REPOS_LOCK: &'static str = "repos.lock";
+    const HEADER: &'static str =
+        "# Generated by `./lila-docker repos lock`, used by `./lila-docker setup --locked`\n\n";
+
+    pub fn load() -> std::io::Result<Self> {
+        let contents = std::fs::read_to_string(Self::REPOS_LOCK).map_err(|err| {
+            Error::new(
+                err.kind(),
+                format!(
+                    "Could not read {}: {err}. Create it with `./lila-docker repos lock`",
+                    Self::REPOS_LOCK
+                ),
+            )
+        })?;
+
+        Self::parse(&contents)
+    }
+
+    fn parse(contents: &str) -> std::io::Result<Self> {
+        toml::from_str(contents).map_err(|err| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid {}: {err}", Self::REPOS_LOCK),
+            )
+        })
+    }
+
+    fn to_toml(&self) -> String {
+        format!("{}{}", Self::HEADER, toml::to_string(&self).unwrap())
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        std::fs::write(Self::REPOS_LOCK, self.to_toml())
+    }
+
+    /// Make the clone loop check out the locked commits, taking precedence over `refs`
+    pub fn pin_refs(&self, config: &mut Config) {
+        config
+            .refs
+            .get_or_insert_with(BTreeMap::new)
+            .extend(self.repos.clone());
+    }
+
+    /// Move checkouts that already existed to their locked commit, unless they have
+    /// uncommitted changes. Shallow checkouts fetch a missing commit with their clone depth.
+    pub fn checkout_existing(&self, repos: &[Repository], config: &Config) -> std::io::Result<()> {
+        for repo in repos.iter().filter(|repo| repo.is_cloned()) {
+            let Some(sha) = self.repos.get(&repo.project) else {
+                warning(format!(
+                    "{} is not in {}, leaving it as is",
+                    repo.full_name(),
+                    Self::REPOS_LOCK
+                ))?;
+                continue;
+            };
+
+            if repo.git(&["rev-parse", "HEAD"])? == *sha {
+                continue;
+            }
+
+            if repo.changed_files()? > 0 {
+                warning(format!(
+                    "{} has uncommitted changes, not checking out {sha}",
+                    repo.full_name()
+                ))?;
+                continue;
+            }
+
+            let depth = repo
+                .is_shallow()?
+                .then(|| repo.clone_depth(config).unwrap_or(1));
+            match repo.checkout_commit(sha, depth) {
+                Ok(()) => step(format!("✓ {} at {sha}", repo.full_name()))?,
+                Err(err) => warning(format!("✗ {}: {err}", repo.full_name()))?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// `repos lock [repo...]`: locks every cloned repository, or updates the named ones and
+/// keeps the other entries
+pub fn run(names: &[String]) -> std::io::Result<()> {
+    let mut lockfile = if names.is_empty() || !Path::new(Lockfile::REPOS_LOCK).exists() {
+        intro(format!("Writing {}...", Lockfile::REPOS_LOCK))?;
+        Lockfile::default()
+    } else {
+        intro(format!("Updating {}...", Lockfile::REPOS_LOCK))?;
+        Lockfile::load()?
+    };
+
+    for repo in selected_repos(names)? {
+        if !repo.is_cloned() {
+            warning(format!("{} is not cloned, skipping", repo.full_name()))?;
+            continue;
+        }
+
+        let sha = repo.git(&["rev-parse", "HEAD"])?;
+
+        if repo.changed_files()? > 0 {
+            warning(format!(
+                "{} has uncommitted changes, they are not part of the lockfile",
+                repo.full_name()
+            ))?;
+        }
+
+        if repo
+            .git(&["branch", "--remotes", "--contains", &sha])?
+            .is_empty()
+        {
+            warning(format!(
+                "{sha} of {} is not on any remote branch, push it so others can fetch it",
+                repo.full_name()
+            ))?;
+        }
+
+        step(format!("{} at {sha}", repo.full_name()))?;
+        lockfile.repos.insert(repo.project.clone(), sha);
+    }
+
+    lockfile.save()?;
+
+    outro(format!(
+        "Reproduce these checkouts with `./lila-docker setup --locked`, after sharing {}",
+        Lockfile::REPOS_LOCK
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lockfile_roundtrip() {
+        let lockfile = Lockfile {
+            repos: BTreeMap::from([
+                (
+                    "lila".to_string(),
+                    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4".to_string(),
+                ),
+                (
+                    "lila-ws".to_string(),
+                    "da39a3ee5e6b4b0d3255bfef95601890afd80709".to_string(),
+                ),
+            ]),
+        };
+
+        let contents = lockfile.to_toml();
+        assert!(contents.starts_with("# Generated by"));
+        assert!(contents.contains("lila = \"e3b0c44298fc1c149afbf4c8996fb92427ae41e4\""));
+        assert_eq!(Lockfile::parse(&contents).unwrap(), lockfile);
+
+        assert_eq!(
+            Lockfile::parse("repos = 1").unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_pin_refs_overrides_config() {
+        let lockfile = Lockfile {
+            repos: BTreeMap::from([(
+                "lila".to_string(),
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4".to_string(),
+            )]),
+        };
+        let mut config = Config {
+            refs: Some(BTreeMap::from([
+                ("lila".to_string(), "master".to_string()),
+                ("scalachess".to_string(), "v16.0.0".to_string()),
+            ])),
+            ..Default::default()
+        };
+
+        lockfile.pin_refs(&mut config);
+
+        assert_eq!(
+            config.refs,
+            Some(BTreeMap::from([
+                (
+                    "lila".to_string(),
+                    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4".to_string()
+                ),
+                ("scalachess".to_string(), "v16.0.0".to_string()),
+            ]))
+        );
+    }
+}
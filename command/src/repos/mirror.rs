@@ -0,0 +1,281 @@
+use super::{git_in as git, selected_repos, Repository};
+use crate::Config;
+use cliclack::{intro, log::warning, outro, spinner};
+use std::{
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+impl Repository {
+    /// Where the bare copy of the repository lives in the mirror: `<mirror>/<org>/<project>.git`
+    pub fn mirror_path(&self, config: &Config) -> Option<PathBuf> {
+        let root = config.mirror.as_deref()?;
+        let root = root.strip_prefix("file://").unwrap_or(root);
+
+        Some(
+            Path::new(root)
+                .join(&self.org)
+                .join(format!("{}.git", self.project)),
+        )
+    }
+
+    /// The mirror copy, if the mirror is configured and has branches of this repository
+    pub fn available_mirror(&self, config: &Config) -> Option<PathBuf> {
+        self.mirror_path(config)
+            .filter(|path| path.exists() && has_branches(path))
+    }
+
+    /// Submodules as `(name, repository)`, read from `.gitmodules`.
+    /// Relative URLs like `../project.git` are resolved against this repository's org.
+    fn submodule_repos(&self) -> Vec<(String, Repository)> {
+        let Ok(urls) = self.git(&[
+            "config",
+            "--file",
+            ".gitmodules",
+            "--get-regexp",
+            r"^submodule\..*\.url$",
+        ]) else {
+            return vec![];
+        };
+
+        parse_submodule_urls(&urls)
+            .into_iter()
+            .map(|(name, url)| {
+                let repo = match url.strip_prefix("../") {
+                    Some(project) => Self::new(&self.org, project.trim_end_matches(".git")),
+                    None => Self::from_url(&url, None),
+                };
+                (name, repo)
+            })
+            .collect()
+    }
+
+    /// After cloning from the mirror: check out the pinned commit and fetch submodules from
+    /// the mirror as well when it has them, then point `upstream` back at the original
+    /// repository
+    pub fn finish_mirror_clone(&self, config: &Config, sha: Option<&str>) -> std::io::Result<()> {
+        if let Some(sha) = sha {
+            self.checkout_detached(sha, self.clone_depth(config))?;
+        }
+        self.init_mirror_submodules(config)?;
+        self.git(&["remote", "set-url", "upstream", &self.url()])?;
+        Ok(())
+    }
+
+    fn init_mirror_submodules(&self, config: &Config) -> std::io::Result<()> {
+        let submodules = self.submodule_repos();
+        if submodules.is_empty() {
+            return Ok(());
+        }
+
+        self.git(&["submodule", "init"])?;
+        for (name, repo) in submodules {
+            if let Some(mirror) = repo.available_mirror(config) {
+                self.git(&[
+                    "config",
+                    &format!("submodule.{name}.url"),
+                    &file_url(&mirror)?,
+                ])?;
+            }
+        }
+
+        // Git refuses local submodule URLs unless explicitly allowed
+        self.git(&[
+            "-c",
+            "protocol.file.allow=always",
+            "submodule",
+            "update",
+            "--recursive",
+        ])?;
+        Ok(())
+    }
+
+    /// Mirror this checkout and its initialized submodules
+    fn update_mirrors(&self, config: &Config) -> std::io::Result<usize> {
+        let mirror = self.mirror_path(config).ok_or_else(no_mirror)?;
+        update_mirror(&self.clone_path(), self.main_remote(), &mirror, config)?;
+        let mut count = 1;
+
+        for (name, repo) in self.submodule_repos() {
+            let path = self.git(&[
+                "config",
+                "--file",
+                ".gitmodules",
+                &format!("submodule.{name}.path"),
+            ])?;
+            let checkout = self.clone_path().join(path);
+
+            if checkout.join(".git").exists() {
+                let mirror = repo.mirror_path(config).ok_or_else(no_mirror)?;
+                update_mirror(&checkout, "origin", &mirror, config)?;
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+pub fn file_url(path: &Path) -> std::io::Result<String> {
+    Ok(format!("file://{}", std::fs::canonicalize(path)?.display()))
+}
+
+/// `submodule.<name>.url <url>` lines of `git config --get-regexp`
+fn parse_submodule_urls(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (key, url) = line.split_once(' ')?;
+            let name = key.strip_prefix("submodule.")?.strip_suffix(".url")?;
+            Some((name.to_string(), url.trim().to_string()))
+        })
+        .collect()
+}
+
+fn has_branches(mirror: &Path) -> bool {
+    git(mirror, &["for-each-ref", "--count=1", "refs/heads"]).is_ok_and(|refs| !refs.is_empty())
+}
+
+/// Create or refresh a bare copy of the checkout at `source` in the mirror: its branches
+/// from `remote` become the branches of the mirror, and the checked out commit is kept as well.
+/// A shallow checkout makes a shallow mirror.
+fn update_mirror(
+    source: &Path,
+    remote: &str,
+    mirror: &Path,
+    config: &Config,
+) -> std::io::Result<()> {
+    if !mirror.exists() {
+        std::fs::create_dir_all(mirror)?;
+        git(mirror, &["init", "--quiet", "--bare"])?;
+        // Lets clones fetch pinned commits and submodule commits by SHA
+        git(mirror, &["config", "uploadpack.allowAnySHA1InWant", "true"])?;
+    }
+
+    let branches = git(
+        source,
+        &[
+            "for-each-ref",
+            "--format=%(refname:strip=3)",
+            &format!("refs/remotes/{remote}"),
+        ],
+    )?;
+
+    let mut refspecs: Vec<String> = branches
+        .lines()
+        .filter(|branch| *branch != "HEAD")
+        .map(|branch| format!("+refs/remotes/{remote}/{branch}:refs/heads/{branch}"))
+        .collect();
+    refspecs.push("+HEAD:refs/lila-docker/checkout".to_string());
+    refspecs.push("+refs/tags/*:refs/tags/*".to_string());
+
+    let source_path = std::fs::canonicalize(source)?.display().to_string();
+    // Without it, git rejects the refs of a shallow checkout but still succeeds
+    let mut args = vec!["fetch", "--quiet", "--update-shallow"];
+    // Clones made with `--reference` borrow objects from the mirror, and a gc after
+    // pruning a branch could delete objects they still need
+    if !config.mirror_reference.unwrap_or_default() {
+        args.push("--prune");
+    }
+    args.push(source_path.as_str());
+    args.extend(refspecs.iter().map(String::as_str));
+    git(mirror, &args)?;
+
+    if !has_branches(mirror) {
+        return Err(Error::other(format!(
+            "No branch of {} was mirrored, fetch them with `./lila-docker repos unshallow`",
+            source.display()
+        )));
+    }
+
+    if let Ok(default_branch) = git(
+        source,
+        &[
+            "symbolic-ref",
+            "--short",
+            &format!("refs/remotes/{remote}/HEAD"),
+        ],
+    ) {
+        let branch = default_branch.trim_start_matches(&format!("{remote}/"));
+        git(
+            mirror,
+            &["symbolic-ref", "HEAD", &format!("refs/heads/{branch}")],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn no_mirror() -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        "No mirror configured, set `mirror` in settings.toml",
+    )
+}
+
+/// `repos mirror [repo...]`
+pub fn run(names: &[String], config: &Config) -> std::io::Result<()> {
+    let root = config.mirror.as_deref().ok_or_else(no_mirror)?;
+
+    intro(format!("Updating the mirror in {root}..."))?;
+
+    for repo in selected_repos(names)? {
+        if !repo.is_cloned() {
+            warning(format!("{} is not cloned, skipping", repo.full_name()))?;
+            continue;
+        }
+
+        let progress = spinner();
+        progress.start(format!("Mirroring {}...", repo.full_name()));
+
+        match repo.update_mirrors(config) {
+            Ok(1) => progress.stop(format!("✓ {}", repo.full_name())),
+            Ok(count) => progress.stop(format!(
+                "✓ {} and {} submodules",
+                repo.full_name(),
+                count - 1
+            )),
+            Err(err) => progress.error(format!("✗ {}: {err}", repo.full_name())),
+        }
+    }
+
+    outro("New setups with this mirror configured will clone from it")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mirror_path() {
+        let lila = Repository::new("lichess-org", "lila");
+        assert_eq!(lila.mirror_path(&Config::default()), None);
+
+        for mirror in ["/srv/mirror", "file:///srv/mirror"] {
+            let config = Config {
+                mirror: Some(mirror.to_string()),
+                ..Default::default()
+            };
+            assert_eq!(
+                lila.mirror_path(&config),
+                Some(PathBuf::from("/srv/mirror/lichess-org/lila.git"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_submodule_urls() {
+        assert_eq!(
+            parse_submodule_urls(
+                "submodule.ui/@build/cssVars.url https://github.com/lichess-org/css-vars\nsubmodule.vendor.url ../vendor.git\n"
+            ),
+            vec![
+                (
+                    "ui/@build/cssVars".to_string(),
+                    "https://github.com/lichess-org/css-vars".to_string()
+                ),
+                ("vendor".to_string(), "../vendor.git".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,492 @@
+use super::{aligned, git_error_summary, parallel_map, selected_repos, Repository};
+use cliclack::{intro, log::warning, note, outro, spinner};
+use serde::Serialize;
+use std::io::{Error, ErrorKind};
+
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub struct RepoStatus {
+    pub repository: String,
+    /// `None` when HEAD is detached
+    pub branch: Option<String>,
+    /// As advertised by the remote HEAD, e.g. `master` for lila and `main` for chessground
+    pub default_branch: Option<String>,
+    /// Commits on HEAD that are not on the remote default branch, and vice versa
+    pub ahead: Option<u32>,
+    pub behind: Option<u32>,
+    /// Modified, staged or deleted tracked files
+    pub changed_files: usize,
+    pub submodules: SubmoduleSummary,
+    pub last_commit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fetch_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pull: Option<PullResult>,
+}
+
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub struct SubmoduleSummary {
+    pub total: usize,
+    pub uninitialized: usize,
+    /// Checked out at a different commit than the one recorded in the superproject
+    pub modified: usize,
+    pub conflicted: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SubmoduleState {
+    UpToDate,
+    Uninitialized,
+    /// Checked out at a different commit than the one recorded in the superproject
+    WrongCommit,
+    Conflicted,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Submodule {
+    /// Relative to the superproject, including nested submodules
+    pub path: String,
+    /// The checked out commit, or the recorded one if not initialized
+    pub commit: String,
+    pub state: SubmoduleState,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "result", content = "detail", rename_all = "snake_case")]
+pub enum PullResult {
+    UpToDate,
+    Updated(u32),
+    Skipped(String),
+    Failed(String),
+}
+
+impl Repository {
+    /// The branch the remote HEAD points to, without the remote prefix
+    pub fn default_branch(&self) -> Option<String> {
+        let remote = self.main_remote();
+        self.git(&[
+            "symbolic-ref",
+            "--short",
+            &format!("refs/remotes/{remote}/HEAD"),
+        ])
+        .ok()
+        .and_then(|head| {
+            head.strip_prefix(&format!("{remote}/"))
+                .map(ToString::to_string)
+        })
+    }
+
+    /// Fetch the main remote, asking it for its HEAD if the checkout does not know it yet
+    pub fn fetch(&self) -> std::io::Result<()> {
+        let remote = self.main_remote();
+        self.git(&["fetch", "--quiet", remote])?;
+
+        if self.default_branch().is_none() {
+            self.git(&["remote", "set-head", remote, "--auto"])?;
+        }
+
+        Ok(())
+    }
+
+    pub fn current_branch(&self) -> Option<String> {
+        self.git(&["branch", "--show-current"])
+            .ok()
+            .filter(|branch| !branch.is_empty())
+    }
+
+    pub fn changed_files(&self) -> std::io::Result<usize> {
+        Ok(self
+            .git(&["status", "--porcelain", "--untracked-files=no"])?
+            .lines()
+            .count())
+    }
+
+    pub fn submodule_list(&self) -> std::io::Result<Vec<Submodule>> {
+        Ok(parse_submodule_status(&self.git(&[
+            "submodule",
+            "status",
+            "--recursive",
+        ])?))
+    }
+
+    pub fn submodules(&self) -> std::io::Result<SubmoduleSummary> {
+        Ok(SubmoduleSummary::new(&self.submodule_list()?))
+    }
+
+    fn ahead_behind(&self, default_branch: &str) -> Option<(u32, u32)> {
+        self.git(&[
+            "rev-list",
+            "--left-right",
+            "--count",
+            &format!("HEAD...{}/{default_branch}", self.main_remote()),
+        ])
+        .ok()
+        .and_then(|output| parse_ahead_behind(&output))
+    }
+
+    pub fn status(&self) -> RepoStatus {
+        let default_branch = self.default_branch();
+        let (ahead, behind) = default_branch
+            .as_deref()
+            .and_then(|branch| self.ahead_behind(branch))
+            .unzip();
+
+        RepoStatus {
+            repository: self.full_name(),
+            branch: self.current_branch(),
+            default_branch,
+            ahead,
+            behind,
+            changed_files: self.changed_files().unwrap_or_default(),
+            submodules: self.submodules().unwrap_or_default(),
+            last_commit: self.git(&["log", "-1", "--format=%h %s (%cr)"]).ok(),
+            ..Default::default()
+        }
+    }
+
+    /// Fast-forward the default branch, leaving feature branches and uncommitted work alone
+    fn pull(&self, status: &RepoStatus) -> PullResult {
+        let Some(default_branch) = &status.default_branch else {
+            return PullResult::Skipped("default branch unknown".to_string());
+        };
+
+        if status.branch.as_ref() != Some(default_branch) {
+            return PullResult::Skipped(format!("not on {default_branch}"));
+        }
+
+        if status.changed_files > 0 {
+            return PullResult::Skipped("uncommitted changes".to_string());
+        }
+
+        match status.behind {
+            Some(0) => return PullResult::UpToDate,
+            None => return PullResult::Skipped("no common history with upstream".to_string()),
+            Some(_) => {}
+        }
+
+        let upstream = format!("{}/{default_branch}", self.main_remote());
+        let result = self
+            .git(&["merge", "--ff-only", "--quiet", &upstream])
+            .and_then(|_| {
+                if status.submodules.total > 0 {
+                    self.git(&["submodule", "update", "--init", "--recursive"])?;
+                }
+                Ok(())
+            });
+
+        match result {
+            Ok(()) => PullResult::Updated(status.behind.unwrap_or_default()),
+            Err(err) => PullResult::Failed(git_error_summary(&err.to_string())),
+        }
+    }
+}
+
+/// `git rev-list --left-right --count` prints `<ahead>\t<behind>`
+fn parse_ahead_behind(output: &str) -> Option<(u32, u32)> {
+    let mut counts = output.split_whitespace().map(str::parse::<u32>);
+    match (counts.next(), counts.next()) {
+        (Some(Ok(ahead)), Some(Ok(behind))) => Some((ahead, behind)),
+        _ => None,
+    }
+}
+
+/// Each line of `git submodule status` starts with ` `, `-` (not initialized),
+/// `+` (different commit checked out) or `U` (merge conflicts), followed by the commit
+/// and the path
+pub fn parse_submodule_status(output: &str) -> Vec<Submodule> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut chars = line.chars();
+            let state = match chars.next()? {
+                '-' => SubmoduleState::Uninitialized,
+                '+' => SubmoduleState::WrongCommit,
+                'U' => SubmoduleState::Conflicted,
+                _ => SubmoduleState::UpToDate,
+            };
+            let mut fields = chars.as_str().split_whitespace();
+            Some(Submodule {
+                commit: fields.next()?.to_string(),
+                path: fields.next()?.to_string(),
+                state,
+            })
+        })
+        .collect()
+}
+
+impl SubmoduleSummary {
+    fn new(submodules: &[Submodule]) -> Self {
+        let count = |state| submodules.iter().filter(|s| s.state == state).count();
+
+        Self {
+            total: submodules.len(),
+            uninitialized: count(SubmoduleState::Uninitialized),
+            modified: count(SubmoduleState::WrongCommit),
+            conflicted: count(SubmoduleState::Conflicted),
+        }
+    }
+
+    fn describe(&self) -> String {
+        if self.total == 0 {
+            return "-".to_string();
+        }
+
+        let problems: Vec<String> = [
+            (self.uninitialized, "uninitialized"),
+            (self.modified, "modified"),
+            (self.conflicted, "conflicted"),
+        ]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{count} {label}"))
+        .collect();
+
+        if problems.is_empty() {
+            format!("{} ok", self.total)
+        } else {
+            problems.join(", ")
+        }
+    }
+}
+
+impl RepoStatus {
+    fn columns(&self) -> [String; 6] {
+        let branch = self.branch.clone().unwrap_or("(detached)".to_string());
+        let upstream = match (self.ahead, self.behind) {
+            (Some(0), Some(0)) => "up to date".to_string(),
+            (Some(ahead), Some(behind)) => format!("↑{ahead} ↓{behind}"),
+            _ => "?".to_string(),
+        };
+        let changes = match self.changed_files {
+            0 => "clean".to_string(),
+            count => format!("{count} changed"),
+        };
+        let last_column = match (&self.pull, &self.fetch_error) {
+            (Some(PullResult::UpToDate), _) => "up to date".to_string(),
+            (Some(PullResult::Updated(commits)), _) => format!("pulled {commits} commits"),
+            (Some(PullResult::Skipped(reason)), _) => format!("skipped: {reason}"),
+            (Some(PullResult::Failed(reason)), _) => format!("failed: {reason}"),
+            (None, Some(err)) => format!("fetch failed: {err}"),
+            (None, None) => self.last_commit.clone().unwrap_or_default(),
+        };
+
+        [
+            self.repository.clone(),
+            branch,
+            upstream,
+            changes,
+            self.submodules.describe(),
+            last_column,
+        ]
+    }
+}
+
+fn status_table(statuses: &[RepoStatus], last_header: &str) -> String {
+    let header = [
+        "REPOSITORY",
+        "BRANCH",
+        "UPSTREAM",
+        "CHANGES",
+        "SUBMODULES",
+        last_header,
+    ]
+    .map(ToString::to_string);
+    let rows: Vec<[String; 6]> = std::iter::once(header)
+        .chain(statuses.iter().map(RepoStatus::columns))
+        .collect();
+
+    aligned(&rows)
+}
+
+struct Options {
+    json: bool,
+    fetch: bool,
+    names: Vec<String>,
+}
+
+fn parse_options(args: &[String]) -> std::io::Result<Options> {
+    let mut options = Options {
+        json: false,
+        fetch: true,
+        names: vec![],
+    };
+
+    for arg in args {
+        match arg.as_str() {
+            "--json" => options.json = true,
+            "--no-fetch" => options.fetch = false,
+            flag if flag.starts_with("--") => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Unknown option {flag}"),
+                ))
+            }
+            name => options.names.push(name.to_string()),
+        }
+    }
+
+    Ok(options)
+}
+
+/// `repos status [--json] [--no-fetch] [repo...]` and `repos pull [--json] [repo...]`
+pub fn run(args: &[String], pull: bool) -> std::io::Result<()> {
+    let options = parse_options(args)?;
+    let repos: Vec<Repository> = selected_repos(&options.names)?
+        .into_iter()
+        .filter(Repository::is_cloned)
+        .collect();
+
+    if !options.json {
+        intro(if pull {
+            "Pulling repositories..."
+        } else {
+            "Repository status"
+        })?;
+    }
+
+    let progress = spinner();
+    if !options.json && (options.fetch || pull) {
+        progress.start(format!("Fetching {} repositories...", repos.len()));
+    }
+
+    let statuses = parallel_map(&repos, |_, repo| {
+        let fetch_error = (options.fetch || pull)
+            .then(|| repo.fetch().err())
+            .flatten()
+            .map(|err| git_error_summary(&err.to_string()));
+
+        let mut status = repo.status();
+        if pull {
+            status.pull = Some(match &fetch_error {
+                Some(err) => PullResult::Failed(format!("fetch failed: {err}")),
+                None => repo.pull(&status),
+            });
+
+            if matches!(status.pull, Some(PullResult::Updated(_))) {
+                status = RepoStatus {
+                    pull: status.pull,
+                    ..repo.status()
+                };
+            }
+        }
+
+        RepoStatus {
+            fetch_error,
+            ..status
+        }
+    });
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&statuses)?);
+        return Ok(());
+    }
+
+    if options.fetch || pull {
+        progress.stop("Fetched");
+    }
+
+    let title = if pull { "Pull results" } else { "Status" };
+    note(
+        title,
+        status_table(&statuses, if pull { "RESULT" } else { "LAST COMMIT" }),
+    )?;
+
+    let failed = statuses.iter().filter(|s| s.fetch_error.is_some()).count();
+    if failed > 0 {
+        warning(format!(
+            "Could not fetch {failed} repositories, their counts may be outdated"
+        ))?;
+    }
+
+    outro("Ahead/behind counts are relative to the remote default branch")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ahead_behind() {
+        assert_eq!(parse_ahead_behind("2\t15"), Some((2, 15)));
+        assert_eq!(parse_ahead_behind("0\t0\n"), Some((0, 0)));
+        assert_eq!(parse_ahead_behind("fatal: bad revision"), None);
+    }
+
+    #[test]
+    fn test_parse_submodule_status() {
+        let output = " 6b1b5f4e0d2a7d3c1f0b2e8a9c4d5e6f7a8b9c0d ui/@build/cssVars (v1.0)\n\
+                      -1d2e3f4a5b6c7d8e9f0a1b2c3d4e5f6a7b8c9d0e public/vendor/stockfish\n\
+                      +9f8e7d6c5b4a3f2e1d0c9b8a7f6e5d4c3b2a1f0e public/piece-css (heads/main)\n";
+
+        let submodules = parse_submodule_status(output);
+        assert_eq!(
+            submodules[2],
+            Submodule {
+                path: "public/piece-css".to_string(),
+                commit: "9f8e7d6c5b4a3f2e1d0c9b8a7f6e5d4c3b2a1f0e".to_string(),
+                state: SubmoduleState::WrongCommit,
+            }
+        );
+
+        let summary = SubmoduleSummary::new(&submodules);
+        assert_eq!(
+            summary,
+            SubmoduleSummary {
+                total: 3,
+                uninitialized: 1,
+                modified: 1,
+                conflicted: 0,
+            }
+        );
+        assert_eq!(summary.describe(), "1 uninitialized, 1 modified");
+        assert_eq!(SubmoduleSummary::new(&[]).describe(), "-");
+    }
+
+    #[test]
+    fn test_status_table() {
+        let statuses = [
+            RepoStatus {
+                repository: "lichess-org/lila".to_string(),
+                branch: Some("master".to_string()),
+                default_branch: Some("master".to_string()),
+                ahead: Some(0),
+                behind: Some(3),
+                changed_files: 2,
+                submodules: SubmoduleSummary {
+                    total: 2,
+                    ..Default::default()
+                },
+                last_commit: Some("abc1234 Fix the clock (2 days ago)".to_string()),
+                ..Default::default()
+            },
+            RepoStatus {
+                repository: "lichess-org/lila-ws".to_string(),
+                branch: None,
+                ahead: Some(0),
+                behind: Some(0),
+                fetch_error: Some("Could not resolve host: github.com".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        assert_eq!(
+            status_table(&statuses, "LAST COMMIT"),
+            [
+                "REPOSITORY           BRANCH      UPSTREAM    CHANGES    SUBMODULES  LAST COMMIT",
+                "lichess-org/lila     master      ↑0 ↓3       2 changed  2 ok        abc1234 Fix the clock (2 days ago)",
+                "lichess-org/lila-ws  (detached)  up to date  clean      -           fetch failed: Could not resolve host: github.com",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let args = ["--json", "lila", "--no-fetch"].map(ToString::to_string);
+        let options = parse_options(&args).unwrap();
+        assert!(options.json);
+        assert!(!options.fetch);
+        assert_eq!(options.names, vec!["lila"]);
+
+        assert!(parse_options(&["--force".to_string()]).is_err());
+    }
+}
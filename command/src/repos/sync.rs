@@ -0,0 +1,433 @@
+use super::{
+    aligned, git_error_summary, parallel_map, selected_repos, status::RepoStatus, Repository,
+};
+use cliclack::{intro, log::info, note, outro, select, spinner};
+use std::io::{Error, ErrorKind};
+
+const USAGE: &str = "Usage: sync [--rebase|--merge] [repo...]";
+
+const STASH_MESSAGE: &str = "lila-docker sync";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Strategy {
+    Rebase,
+    Merge,
+    /// Leave feature branches alone, only fast-forward default branches
+    Skip,
+}
+
+/// What syncing a checkout will do, decided from its status after fetching
+#[derive(Debug, PartialEq)]
+enum Plan {
+    UpToDate,
+    Skip(String),
+    /// On the default branch: fast-forward it
+    FastForward {
+        upstream: String,
+        behind: u32,
+    },
+    /// On a feature branch: rebase or merge the updated default branch into it
+    Integrate {
+        upstream: String,
+        behind: u32,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+enum SyncResult {
+    UpToDate,
+    Skipped(String),
+    Updated {
+        done: String,
+        /// Uncommitted changes were stashed, and could be put back unless `false`
+        stash_restored: Option<bool>,
+    },
+    /// The rebase or merge was aborted, the checkout is back where it was
+    Conflict {
+        files: Vec<String>,
+        stash_restored: Option<bool>,
+    },
+    Failed(String),
+}
+
+impl SyncResult {
+    fn describe(&self) -> String {
+        let stash_note = |restored: &Option<bool>| match restored {
+            Some(true) => ", uncommitted changes restored",
+            Some(false) => ", uncommitted changes conflict, kept in `git stash list`",
+            None => "",
+        };
+
+        match self {
+            Self::UpToDate => "up to date".to_string(),
+            Self::Skipped(reason) => format!("skipped: {reason}"),
+            Self::Updated {
+                done,
+                stash_restored,
+            } => format!("✓ {done}{}", stash_note(stash_restored)),
+            Self::Conflict {
+                files,
+                stash_restored,
+            } => format!(
+                "✗ conflicts in {}, aborted{}",
+                files.join(", "),
+                stash_note(stash_restored)
+            ),
+            Self::Failed(reason) => format!("✗ {reason}"),
+        }
+    }
+}
+
+fn plan(status: &RepoStatus, remote: &str) -> Plan {
+    if let Some(err) = &status.fetch_error {
+        return Plan::Skip(format!("fetch failed: {err}"));
+    }
+    let Some(default_branch) = &status.default_branch else {
+        return Plan::Skip("default branch unknown".to_string());
+    };
+    let Some(branch) = &status.branch else {
+        return Plan::Skip("detached HEAD".to_string());
+    };
+    let upstream = format!("{remote}/{default_branch}");
+
+    match status.behind {
+        None => Plan::Skip("no common history with upstream".to_string()),
+        Some(0) => Plan::UpToDate,
+        Some(behind) if branch == default_branch => Plan::FastForward { upstream, behind },
+        Some(behind) => Plan::Integrate { upstream, behind },
+    }
+}
+
+/// `git diff --name-only --diff-filter=U` lists the conflicted files
+fn conflicted_files(output: &str) -> Vec<String> {
+    output.lines().map(ToString::to_string).collect()
+}
+
+impl Repository {
+    /// A rebase or merge the user has not finished, which sync must not interfere with
+    fn operation_in_progress(&self) -> Option<&'static str> {
+        [
+            ("rebase-merge", "rebase"),
+            ("rebase-apply", "rebase"),
+            ("MERGE_HEAD", "merge"),
+        ]
+        .into_iter()
+        .find(|(path, _)| {
+            self.git(&["rev-parse", "--git-path", path])
+                .is_ok_and(|path| self.clone_path().join(path).exists())
+        })
+        .map(|(_, operation)| operation)
+    }
+
+    /// Stash the uncommitted changes, returning whether there was anything to stash:
+    /// changes in submodules are not stashed, and an older stash must not be popped
+    fn stash(&self) -> std::io::Result<bool> {
+        let top = || {
+            self.git(&["rev-parse", "--quiet", "--verify", "refs/stash"])
+                .ok()
+        };
+        let before = top();
+        self.git(&["stash", "push", "--quiet", "--message", STASH_MESSAGE])?;
+        Ok(top() != before)
+    }
+
+    fn integrate(&self, strategy: Strategy, upstream: &str) -> std::io::Result<()> {
+        match strategy {
+            Strategy::Rebase => self.git(&["rebase", "--quiet", upstream]),
+            Strategy::Merge => self.git(&["merge", "--quiet", "--no-edit", upstream]),
+            Strategy::Skip => return Ok(()),
+        }
+        .map(|_| ())
+    }
+
+    fn abort(&self, strategy: Strategy) {
+        let _ = match strategy {
+            Strategy::Rebase => self.git(&["rebase", "--abort"]),
+            Strategy::Merge => self.git(&["merge", "--abort"]),
+            Strategy::Skip => return,
+        };
+    }
+
+    fn sync(&self, plan: &Plan, strategy: Strategy, dirty: bool) -> SyncResult {
+        let (upstream, behind, strategy) = match plan {
+            Plan::UpToDate => return SyncResult::UpToDate,
+            Plan::Skip(reason) => return SyncResult::Skipped(reason.clone()),
+            Plan::Integrate { .. } if strategy == Strategy::Skip => {
+                return SyncResult::Skipped("feature branch".to_string())
+            }
+            Plan::FastForward { upstream, behind } => (upstream, behind, None),
+            Plan::Integrate { upstream, behind } => (upstream, behind, Some(strategy)),
+        };
+
+        if let Some(operation) = self.operation_in_progress() {
+            return SyncResult::Skipped(format!("{operation} in progress"));
+        }
+
+        let stashed = if dirty {
+            match self.stash() {
+                Ok(stashed) => stashed,
+                Err(err) => {
+                    return SyncResult::Failed(format!(
+                        "could not stash: {}",
+                        git_error_summary(&err.to_string())
+                    ))
+                }
+            }
+        } else {
+            false
+        };
+        let restore_stash = || stashed.then(|| self.git(&["stash", "pop", "--quiet"]).is_ok());
+
+        let result = match strategy {
+            None => self
+                .git(&["merge", "--ff-only", "--quiet", upstream])
+                .map(|_| ()),
+            Some(strategy) => self.integrate(strategy, upstream),
+        };
+
+        if let Err(err) = result {
+            let files = self
+                .git(&["diff", "--name-only", "--diff-filter=U"])
+                .map(|output| conflicted_files(&output))
+                .unwrap_or_default();
+            if let Some(strategy) = strategy {
+                self.abort(strategy);
+            }
+            if let Some(operation) = self.operation_in_progress() {
+                return SyncResult::Failed(format!(
+                    "the {operation} could not be aborted, finish it or run `git {operation} --abort`"
+                ));
+            }
+            let stash_restored = restore_stash();
+
+            return if files.is_empty() {
+                SyncResult::Failed(git_error_summary(&err.to_string()))
+            } else {
+                SyncResult::Conflict {
+                    files,
+                    stash_restored,
+                }
+            };
+        }
+
+        let submodules = self.git(&["submodule", "update", "--init", "--recursive"]);
+        let stash_restored = restore_stash();
+
+        if let Err(err) = submodules {
+            return SyncResult::Failed(format!(
+                "updated, but the submodules failed: {}",
+                git_error_summary(&err.to_string())
+            ));
+        }
+
+        SyncResult::Updated {
+            done: match strategy {
+                None => format!("fast-forwarded {behind} commits"),
+                Some(Strategy::Rebase) => {
+                    format!("rebased onto {behind} new commits of {upstream}")
+                }
+                Some(_) => format!("merged {behind} new commits of {upstream}"),
+            },
+            stash_restored,
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> std::io::Result<(Option<Strategy>, Vec<String>)> {
+    let mut strategy = None;
+    let mut names = vec![];
+
+    for arg in args {
+        match arg.as_str() {
+            "--rebase" => strategy = Some(Strategy::Rebase),
+            "--merge" => strategy = Some(Strategy::Merge),
+            flag if flag.starts_with("--") => {
+                return Err(Error::new(ErrorKind::InvalidInput, USAGE));
+            }
+            name => names.push(name.to_string()),
+        }
+    }
+
+    Ok((strategy, names))
+}
+
+fn prompt_for_strategy(feature_repos: &[String]) -> std::io::Result<Strategy> {
+    select(format!(
+        "Update the feature branches of {} with the new upstream commits?",
+        feature_repos.join(", ")
+    ))
+    .item(
+        Strategy::Rebase,
+        "Rebase",
+        "Replays your commits, pushed branches then need `git push --force-with-lease`",
+    )
+    .item(Strategy::Merge, "Merge", "Adds a merge commit")
+    .item(
+        Strategy::Skip,
+        "Leave them",
+        "Only fast-forward the default branches",
+    )
+    .interact()
+}
+
+/// `repos sync [--rebase|--merge] [repo...]`
+pub fn run(args: &[String]) -> std::io::Result<()> {
+    let (strategy, names) = parse_args(args)?;
+    let repos: Vec<Repository> = selected_repos(&names)?
+        .into_iter()
+        .filter(Repository::is_cloned)
+        .collect();
+
+    intro("Syncing repositories with upstream...")?;
+
+    let progress = spinner();
+    progress.start(format!("Fetching {} repositories...", repos.len()));
+    let statuses = parallel_map(&repos, |_, repo| RepoStatus {
+        fetch_error: repo
+            .fetch()
+            .err()
+            .map(|err| git_error_summary(&err.to_string())),
+        ..repo.status()
+    });
+    progress.stop("Fetched");
+
+    let plans: Vec<Plan> = repos
+        .iter()
+        .zip(&statuses)
+        .map(|(repo, status)| plan(status, repo.main_remote()))
+        .collect();
+
+    let feature_repos: Vec<String> = repos
+        .iter()
+        .zip(&plans)
+        .filter(|(_, plan)| matches!(plan, Plan::Integrate { .. }))
+        .map(|(repo, _)| repo.full_name())
+        .collect();
+
+    let strategy = match strategy {
+        Some(strategy) => strategy,
+        None if feature_repos.is_empty() => Strategy::Skip,
+        None => prompt_for_strategy(&feature_repos)?,
+    };
+
+    let mut rows = vec![];
+    let mut conflicts = vec![];
+    let mut rebased = false;
+
+    for ((repo, status), plan) in repos.iter().zip(&statuses).zip(&plans) {
+        let result = repo.sync(plan, strategy, status.changed_files > 0);
+
+        match (&result, plan) {
+            (SyncResult::Conflict { .. }, Plan::Integrate { upstream, .. }) => {
+                conflicts.push((repo, upstream));
+            }
+            (SyncResult::Updated { .. }, Plan::Integrate { .. }) => {
+                rebased |= strategy == Strategy::Rebase;
+            }
+            _ => {}
+        }
+        rows.push([repo.full_name(), result.describe()]);
+    }
+
+    note("Sync results", aligned(&rows))?;
+
+    for (repo, upstream) in conflicts {
+        info(format!(
+            "Resolve the conflicts of {} by hand: `git -C {} {} {upstream}`",
+            repo.full_name(),
+            repo.clone_path().display(),
+            if strategy == Strategy::Merge {
+                "merge"
+            } else {
+                "rebase"
+            }
+        ))?;
+    }
+
+    if rebased {
+        outro("Rebased branches that were already pushed need `git push --force-with-lease`")
+    } else {
+        outro("Done")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(branch: &str, behind: Option<u32>) -> RepoStatus {
+        RepoStatus {
+            repository: "lichess-org/lila".to_string(),
+            branch: Some(branch.to_string()),
+            default_branch: Some("master".to_string()),
+            behind,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        assert_eq!(plan(&status("master", Some(0)), "upstream"), Plan::UpToDate);
+        assert_eq!(
+            plan(&status("master", Some(3)), "upstream"),
+            Plan::FastForward {
+                upstream: "upstream/master".to_string(),
+                behind: 3
+            }
+        );
+        assert_eq!(
+            plan(&status("my-feature", Some(5)), "origin"),
+            Plan::Integrate {
+                upstream: "origin/master".to_string(),
+                behind: 5
+            }
+        );
+        assert_eq!(
+            plan(&status("my-feature", None), "origin"),
+            Plan::Skip("no common history with upstream".to_string())
+        );
+        assert_eq!(
+            plan(
+                &RepoStatus {
+                    branch: None,
+                    ..status("master", Some(1))
+                },
+                "origin"
+            ),
+            Plan::Skip("detached HEAD".to_string())
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(
+            SyncResult::Updated {
+                done: "rebased onto 2 new commits of upstream/master".to_string(),
+                stash_restored: Some(true),
+            }
+            .describe(),
+            "✓ rebased onto 2 new commits of upstream/master, uncommitted changes restored"
+        );
+        assert_eq!(
+            SyncResult::Conflict {
+                files: conflicted_files("app/Env.scala\nbuild.sbt\n"),
+                stash_restored: None,
+            }
+            .describe(),
+            "✗ conflicts in app/Env.scala, build.sbt, aborted"
+        );
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = ["--merge", "lila", "lila-ws"].map(ToString::to_string);
+        assert_eq!(
+            parse_args(&args).unwrap(),
+            (
+                Some(Strategy::Merge),
+                vec!["lila".to_string(), "lila-ws".to_string()]
+            )
+        );
+        assert!(parse_args(&["--force".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,486 @@
+use super::{git_in, Repository};
+use crate::{compose, Config};
+use cliclack::{intro, log::step, note, outro};
+use std::{
+    collections::BTreeMap,
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+const USAGE: &str = "Usage: worktree <add <repo> <branch> [name] | list [repo...] | use <repo> <name|main> | remove <repo> <name> [--force]>";
+
+/// Compose file that mounts the selected worktrees in place of the main checkouts
+pub const WORKTREES_OVERRIDE: &str = "docker-compose.worktrees.yml";
+
+/// Name of the main checkout for `worktree use`
+const MAIN_WORKTREE: &str = "main";
+
+#[derive(Debug, PartialEq)]
+struct Worktree {
+    path: PathBuf,
+    /// `None` when HEAD is detached
+    branch: Option<String>,
+}
+
+/// A bind mount of a repository in the compose configuration
+#[derive(Debug, PartialEq)]
+struct Mount {
+    service: String,
+    /// Path inside the repository, e.g. `public`, or empty for the whole repository
+    subpath: PathBuf,
+    /// Mount point in the container, e.g. `/lila/public`
+    target: String,
+    read_only: bool,
+}
+
+impl Mount {
+    /// The same mount from the worktree. The `.git` file of a worktree points to the absolute
+    /// path of the main `.git` on the host, which is mounted at that same path for git to
+    /// work in the container.
+    fn worktree_volumes(&self, main: &Path, worktree: &Path) -> Vec<String> {
+        let whole_checkout = self.subpath.as_os_str().is_empty();
+        let source = if whole_checkout {
+            worktree.to_path_buf()
+        } else {
+            worktree.join(&self.subpath)
+        };
+        let mut volumes = vec![format!(
+            "{}:{}{}",
+            source.display(),
+            self.target,
+            if self.read_only { ":ro" } else { "" }
+        )];
+        if whole_checkout {
+            let git_dir = main.join(".git");
+            volumes.push(format!("{}:{}", git_dir.display(), git_dir.display()));
+        }
+        volumes
+    }
+}
+
+impl Repository {
+    /// Worktrees live next to the main checkout: `repos/lila@<name>`
+    fn worktree_path(&self, name: &str) -> PathBuf {
+        let main = self.clone_path();
+        main.with_file_name(format!(
+            "{}@{name}",
+            main.file_name().unwrap_or_default().to_string_lossy()
+        ))
+    }
+
+    /// The checkout the containers mount: the main one or the worktree picked with `worktree use`
+    pub fn mounted_checkout(&self, config: &Config) -> PathBuf {
+        mounted_worktree(config, self)
+            .map_or_else(|| self.clone_path(), |name| self.worktree_path(name))
+    }
+
+    fn worktrees(&self) -> std::io::Result<Vec<Worktree>> {
+        Ok(parse_worktree_list(&self.git(&[
+            "worktree",
+            "list",
+            "--porcelain",
+        ])?))
+    }
+
+    /// Check out the branch in a new worktree, creating the branch from the remote branch
+    /// of the same name or from the current HEAD if needed
+    fn add_worktree(&self, branch: &str, name: &str) -> std::io::Result<PathBuf> {
+        if name == MAIN_WORKTREE {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("`{MAIN_WORKTREE}` is the name of the main checkout"),
+            ));
+        }
+
+        let path = self.worktree_path(name);
+        if path.exists() {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+
+        let absolute_path = std::env::current_dir()?.join(&path);
+        let absolute_path = absolute_path.to_string_lossy();
+        let remote_branch = format!("{}/{branch}", self.main_remote());
+
+        if self.has_local_branch(branch) {
+            self.git(&["worktree", "add", "--quiet", &absolute_path, branch])?;
+        } else if self.has_remote_branch(branch) {
+            self.git(&[
+                "worktree",
+                "add",
+                "--quiet",
+                "--track",
+                "-b",
+                branch,
+                &absolute_path,
+                &remote_branch,
+            ])?;
+        } else {
+            self.git(&["worktree", "add", "--quiet", "-b", branch, &absolute_path])?;
+        }
+
+        git_in(&path, &["submodule", "update", "--init", "--recursive"])?;
+
+        Ok(path)
+    }
+}
+
+/// Parse `git worktree list --porcelain`, whose entries are separated by blank lines
+fn parse_worktree_list(output: &str) -> Vec<Worktree> {
+    output
+        .split("\n\n")
+        .filter_map(|entry| {
+            let mut path = None;
+            let mut branch = None;
+            for line in entry.lines() {
+                if let Some(value) = line.strip_prefix("worktree ") {
+                    path = Some(PathBuf::from(value));
+                } else if let Some(value) = line.strip_prefix("branch ") {
+                    branch = Some(value.trim_start_matches("refs/heads/").to_string());
+                }
+            }
+            Some(Worktree {
+                path: path?,
+                branch,
+            })
+        })
+        .collect()
+}
+
+/// The bind mounts of the checkout in each service, read from the output of
+/// `docker compose config --format json`
+fn repo_mounts(compose_config: &str, checkout: &Path) -> std::io::Result<Vec<Mount>> {
+    let config: serde_json::Value = serde_json::from_str(compose_config)?;
+
+    let Some(services) = config["services"].as_object() else {
+        return Ok(vec![]);
+    };
+
+    Ok(services
+        .iter()
+        .flat_map(|(service, definition)| {
+            definition["volumes"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|volume| volume["type"] == "bind")
+                .filter_map(move |volume| {
+                    let subpath = Path::new(volume["source"].as_str()?)
+                        .strip_prefix(checkout)
+                        .ok()?;
+                    Some(Mount {
+                        service: service.clone(),
+                        subpath: subpath.to_path_buf(),
+                        target: volume["target"].as_str()?.to_string(),
+                        read_only: volume["read_only"].as_bool().unwrap_or_default(),
+                    })
+                })
+        })
+        .collect())
+}
+
+fn override_yml(volumes: &BTreeMap<String, Vec<String>>) -> String {
+    std::iter::once(
+        "# Generated by `./lila-docker worktree use`, do not edit\n\nservices:".to_string(),
+    )
+    .chain(volumes.iter().flat_map(|(service, volumes)| {
+        [format!("  {service}:"), "    volumes:".to_string()]
+            .into_iter()
+            .chain(volumes.iter().map(|volume| format!("      - {volume}")))
+    }))
+    .map(|line| line + "\n")
+    .collect()
+}
+
+/// Regenerate the compose override for the selected worktrees, and add it to (or remove it
+/// from) the compose files of the configuration
+fn write_override(config: &mut Config) -> std::io::Result<()> {
+    let files = config.compose_files.get_or_insert_with(Vec::new);
+    files.retain(|file| file != WORKTREES_OVERRIDE);
+
+    let worktrees = config.worktrees.clone().unwrap_or_default();
+    let compose_config = if worktrees.is_empty() {
+        String::new()
+    } else {
+        compose::docker_compose(config, &["config", "--format", "json"])?
+    };
+
+    let mut volumes: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (project, name) in worktrees {
+        let repo = Repository::find(&project)?;
+        let main = std::fs::canonicalize(repo.clone_path())?;
+        let worktree = std::fs::canonicalize(repo.worktree_path(&name))?;
+
+        for mount in repo_mounts(&compose_config, &main)? {
+            volumes
+                .entry(mount.service.clone())
+                .or_default()
+                .extend(mount.worktree_volumes(&main, &worktree));
+        }
+    }
+
+    let files = config.compose_files.get_or_insert_with(Vec::new);
+    if volumes.is_empty() {
+        if Path::new(WORKTREES_OVERRIDE).exists() {
+            std::fs::remove_file(WORKTREES_OVERRIDE)?;
+        }
+    } else {
+        std::fs::write(WORKTREES_OVERRIDE, override_yml(&volumes))?;
+        files.push(WORKTREES_OVERRIDE.to_string());
+    }
+
+    Ok(())
+}
+
+fn mounted_worktree<'a>(config: &'a Config, repo: &Repository) -> Option<&'a str> {
+    config
+        .worktrees
+        .as_ref()
+        .and_then(|worktrees| worktrees.get(&repo.project))
+        .map(String::as_str)
+}
+
+fn find_cloned(name: &str) -> std::io::Result<Repository> {
+    let repo = Repository::find(name)?;
+    if !repo.is_cloned() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{} is not cloned", repo.full_name()),
+        ));
+    }
+    Ok(repo)
+}
+
+fn list(names: &[String], config: &Config) -> std::io::Result<()> {
+    let mut rows = vec![];
+
+    for repo in super::selected_repos(names)? {
+        let worktrees = repo.worktrees()?;
+        let mounted = mounted_worktree(config, &repo);
+
+        for worktree in worktrees {
+            let name = worktree
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .split_once('@')
+                .map_or(MAIN_WORKTREE.to_string(), |(_, name)| name.to_string());
+            let is_mounted = mounted.unwrap_or(MAIN_WORKTREE) == name;
+
+            rows.push(format!(
+                "{} {:<20} {:<12} {}",
+                if is_mounted { "*" } else { " " },
+                repo.full_name(),
+                name,
+                worktree.branch.as_deref().unwrap_or("(detached)")
+            ));
+        }
+    }
+
+    note("Worktrees (* mounted)", rows.join("\n"))?;
+    outro(format!(
+        "Switch with `./lila-docker worktree use <repo> <name|{MAIN_WORKTREE}>`"
+    ))
+}
+
+fn switch(repo: &Repository, name: &str, mut config: Config) -> std::io::Result<()> {
+    let mounted_path = if name == MAIN_WORKTREE {
+        if let Some(worktrees) = config.worktrees.as_mut() {
+            worktrees.remove(&repo.project);
+        }
+        repo.clone_path()
+    } else {
+        let path = repo.worktree_path(name);
+        if !path.exists() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "No worktree {name} for {}, create it with `./lila-docker worktree add`",
+                    repo.full_name()
+                ),
+            ));
+        }
+        config
+            .worktrees
+            .get_or_insert_with(BTreeMap::new)
+            .insert(repo.project.clone(), name.to_string());
+        path
+    };
+
+    write_override(&mut config)?;
+    config.save()?;
+
+    step(format!(
+        "✓ {} now mounts {}",
+        repo.full_name(),
+        mounted_path.display()
+    ))?;
+
+    compose::offer_restart(&config, &mounted_path)?;
+
+    outro("Done")
+}
+
+fn remove(repo: &Repository, name: &str, force: bool, config: &Config) -> std::io::Result<()> {
+    if mounted_worktree(config, repo) == Some(name) {
+        return Err(Error::other(format!(
+            "Worktree {name} is mounted, run `./lila-docker worktree use {} {MAIN_WORKTREE}` first",
+            repo.project
+        )));
+    }
+
+    let path = repo.worktree_path(name);
+    if !force && !git_in(&path, &["status", "--porcelain"])?.is_empty() {
+        return Err(Error::other(format!(
+            "Worktree {name} has uncommitted or untracked files, use --force to remove it anyway"
+        )));
+    }
+
+    // Git only removes worktrees with submodules when forced, the check above keeps work safe
+    let path = std::env::current_dir()?.join(path);
+    repo.git(&["worktree", "remove", "--force", &path.to_string_lossy()])?;
+
+    outro(format!("Removed worktree {name} of {}", repo.full_name()))
+}
+
+/// `worktree <add|list|use|remove> ...`
+pub fn run(args: &[String], config: Config) -> std::io::Result<()> {
+    let usage = || Error::new(ErrorKind::InvalidInput, USAGE);
+    let force = args.iter().any(|arg| arg == "--force");
+    let args: Vec<&str> = args
+        .iter()
+        .map(String::as_str)
+        .filter(|arg| *arg != "--force")
+        .collect();
+
+    intro("Worktrees")?;
+
+    match args.as_slice() {
+        ["add", repo, branch, rest @ ..] => {
+            let repo = find_cloned(repo)?;
+            let name = rest
+                .first()
+                .map_or(branch.replace('/', "-"), ToString::to_string);
+            let path = repo.add_worktree(branch, &name)?;
+            outro(format!(
+                "Checked out {branch} in {}, mount it with `./lila-docker worktree use {} {name}`",
+                path.display(),
+                repo.project
+            ))
+        }
+        ["list", names @ ..] => list(
+            &names.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            &config,
+        ),
+        ["use", repo, name] => switch(&find_cloned(repo)?, name, config),
+        ["remove", repo, name] => remove(&find_cloned(repo)?, name, force, &config),
+        _ => Err(usage()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_worktree_path() {
+        assert_eq!(
+            Repository::new("lichess-org", "lila").worktree_path("pr-123"),
+            Path::new("repos/lila@pr-123")
+        );
+    }
+
+    #[test]
+    fn test_parse_worktree_list() {
+        let output = "worktree /home/me/lila-docker/repos/lila\nHEAD 1111111111111111111111111111111111111111\nbranch refs/heads/master\n\nworktree /home/me/lila-docker/repos/lila@review\nHEAD 2222222222222222222222222222222222222222\ndetached\n";
+
+        assert_eq!(
+            parse_worktree_list(output),
+            vec![
+                Worktree {
+                    path: PathBuf::from("/home/me/lila-docker/repos/lila"),
+                    branch: Some("master".to_string()),
+                },
+                Worktree {
+                    path: PathBuf::from("/home/me/lila-docker/repos/lila@review"),
+                    branch: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repo_mounts() {
+        let compose_config = r#"{
+            "services": {
+                "lila": {"volumes": [
+                    {"type": "bind", "source": "/home/me/lila-docker/repos/lila", "target": "/lila"},
+                    {"type": "bind", "source": "/home/me/lila-docker/repos/lila-ws", "target": "/lila-ws"}
+                ]},
+                "nginx": {"volumes": [
+                    {"type": "bind", "source": "/home/me/lila-docker/conf/nginx.conf", "target": "/etc/nginx/conf.d/default.conf"},
+                    {"type": "bind", "source": "/home/me/lila-docker/repos/lila/public", "target": "/lila/public", "read_only": true}
+                ]},
+                "mongodb": {"volumes": [
+                    {"type": "volume", "source": "mongodb", "target": "/data/db"}
+                ]},
+                "redis": {}
+            }
+        }"#;
+        let main = Path::new("/home/me/lila-docker/repos/lila");
+
+        let mounts = repo_mounts(compose_config, main).unwrap();
+        assert_eq!(
+            mounts,
+            vec![
+                Mount {
+                    service: "lila".to_string(),
+                    subpath: PathBuf::new(),
+                    target: "/lila".to_string(),
+                    read_only: false,
+                },
+                Mount {
+                    service: "nginx".to_string(),
+                    subpath: PathBuf::from("public"),
+                    target: "/lila/public".to_string(),
+                    read_only: true,
+                },
+            ]
+        );
+
+        let worktree = Path::new("/home/me/lila-docker/repos/lila@review");
+        assert_eq!(
+            mounts[0].worktree_volumes(main, worktree),
+            vec![
+                "/home/me/lila-docker/repos/lila@review:/lila",
+                "/home/me/lila-docker/repos/lila/.git:/home/me/lila-docker/repos/lila/.git",
+            ]
+        );
+        assert_eq!(
+            mounts[1].worktree_volumes(main, worktree),
+            vec!["/home/me/lila-docker/repos/lila@review/public:/lila/public:ro"]
+        );
+    }
+
+    #[test]
+    fn test_override_yml() {
+        let volumes = BTreeMap::from([
+            (
+                "lila".to_string(),
+                vec!["./repos/lila@review:/lila".to_string()],
+            ),
+            (
+                "nginx".to_string(),
+                vec!["./repos/lila@review/public:/lila/public".to_string()],
+            ),
+        ]);
+
+        assert_eq!(
+            override_yml(&volumes),
+            "# Generated by `./lila-docker worktree use`, do not edit\n\nservices:\n  lila:\n    volumes:\n      - ./repos/lila@review:/lila\n  nginx:\n    volumes:\n      - ./repos/lila@review/public:/lila/public\n"
+        );
+    }
+}
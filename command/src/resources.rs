@@ -0,0 +1,245 @@
+use crate::services::OptionalService;
+use std::{fmt, ops::Add, path::Path, process::Command};
+
+/// Approximate requirements of a service while it is running
+#[derive(Default, Clone, Copy, Eq, PartialEq, Debug)]
+pub struct Resources {
+    pub memory_mb: u64,
+    pub cpu_millis: u64,
+    pub disk_mb: u64,
+}
+
+impl Resources {
+    /// mongodb (primary + secondary), redis, lila, lila-ws, nginx and the ui build
+    pub const BASE: Self = Self::new(6144, 4000, 12_000);
+
+    pub const fn new(memory_mb: u64, cpu_millis: u64, disk_mb: u64) -> Self {
+        Self {
+            memory_mb,
+            cpu_millis,
+            disk_mb,
+        }
+    }
+
+    pub fn total<'a>(services: impl IntoIterator<Item = &'a OptionalService>) -> Self {
+        services
+            .into_iter()
+            .fold(Self::BASE, |total, service| total + service.resources)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Add for Resources {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            memory_mb: self.memory_mb + other.memory_mb,
+            cpu_millis: self.cpu_millis + other.cpu_millis,
+            disk_mb: self.disk_mb + other.disk_mb,
+        }
+    }
+}
+
+impl fmt::Display for Resources {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} RAM, {} CPU, {} disk",
+            format_mb(self.memory_mb),
+            format_millis(self.cpu_millis),
+            format_mb(self.disk_mb)
+        )
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn format_mb(mb: u64) -> String {
+    if mb >= 1024 {
+        format!("{:.1} GB", mb as f64 / 1024.0)
+    } else {
+        format!("{mb} MB")
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn format_millis(millis: u64) -> String {
+    format!("{:.1}", millis as f64 / 1000.0)
+}
+
+/// What this machine can provide to the containers. Values that could not be
+/// determined are left empty and are not checked.
+#[derive(Default, Debug, PartialEq)]
+pub struct HostCapacity {
+    pub memory_mb: Option<u64>,
+    pub cpu_millis: Option<u64>,
+    pub disk_mb: Option<u64>,
+}
+
+impl HostCapacity {
+    pub fn detect() -> Self {
+        Self {
+            memory_mb: std::fs::read_to_string("/proc/meminfo")
+                .ok()
+                .and_then(|contents| parse_meminfo(&contents)),
+            cpu_millis: std::thread::available_parallelism()
+                .ok()
+                .map(|cpus| cpus.get() as u64 * 1000),
+            disk_mb: free_disk_mb(Path::new(".")),
+        }
+    }
+
+    /// What could be determined, e.g. `8.0 GB RAM, 4.0 CPU, 150.0 GB free disk`
+    pub fn summary(&self) -> Option<String> {
+        let parts: Vec<String> = [
+            self.memory_mb.map(|mb| format!("{} RAM", format_mb(mb))),
+            self.cpu_millis
+                .map(|millis| format!("{} CPU", format_millis(millis))),
+            self.disk_mb
+                .map(|mb| format!("{} free disk", format_mb(mb))),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        (!parts.is_empty()).then(|| parts.join(", "))
+    }
+
+    /// Human readable descriptions of every resource the selection exceeds
+    pub fn shortfalls(&self, required: Resources) -> Vec<String> {
+        let mut shortfalls = vec![];
+
+        if let Some(memory_mb) = self.memory_mb.filter(|&mb| required.memory_mb > mb) {
+            shortfalls.push(format!(
+                "~{} RAM needed, but this machine has {}",
+                format_mb(required.memory_mb),
+                format_mb(memory_mb)
+            ));
+        }
+
+        if let Some(cpu_millis) = self.cpu_millis.filter(|&m| required.cpu_millis > m) {
+            shortfalls.push(format!(
+                "~{} CPUs needed, but this machine has {}",
+                format_millis(required.cpu_millis),
+                format_millis(cpu_millis)
+            ));
+        }
+
+        if let Some(disk_mb) = self.disk_mb.filter(|&mb| required.disk_mb > mb) {
+            shortfalls.push(format!(
+                "~{} of disk needed, but only {} is free",
+                format_mb(required.disk_mb),
+                format_mb(disk_mb)
+            ));
+        }
+
+        shortfalls
+    }
+}
+
+fn parse_meminfo(contents: &str) -> Option<u64> {
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))
+        .and_then(|value| {
+            value
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()
+        })
+        .map(|kb| kb / 1024)
+}
+
+fn free_disk_mb(path: &Path) -> Option<u64> {
+    let output = Command::new("df").arg("-Pk").arg(path).output().ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    parse_df(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_df(output: &str) -> Option<u64> {
+    output
+        .lines()
+        .nth(1)
+        .and_then(|line| line.split_whitespace().nth(3))
+        .and_then(|available| available.parse::<u64>().ok())
+        .map(|kb| kb / 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_total_includes_base() {
+        assert_eq!(Resources::total(&[]), Resources::BASE);
+
+        let services = [
+            OptionalService::builtin()
+                .into_iter()
+                .find(|s| s.name == "mongo-express")
+                .unwrap(),
+            OptionalService::builtin()
+                .into_iter()
+                .find(|s| s.name == "search")
+                .unwrap(),
+        ];
+
+        assert_eq!(
+            Resources::total(&services),
+            Resources::BASE + services[0].resources + services[1].resources
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            Resources::new(512, 1500, 2048).to_string(),
+            "512 MB RAM, 1.5 CPU, 2.0 GB disk"
+        );
+    }
+
+    #[test]
+    fn test_parse_meminfo() {
+        let contents = "MemTotal:        8048572 kB\nMemFree:          512000 kB\n";
+        assert_eq!(parse_meminfo(contents), Some(7859));
+        assert_eq!(parse_meminfo("garbage"), None);
+    }
+
+    #[test]
+    fn test_parse_df() {
+        let output = "Filesystem     1024-blocks      Used Available Capacity Mounted on\n/dev/nvme0n1p2   486903968 301234112 160854776      66% /\n";
+        assert_eq!(parse_df(output), Some(157_084));
+        assert_eq!(parse_df(""), None);
+    }
+
+    #[test]
+    fn test_shortfalls() {
+        let host = HostCapacity {
+            memory_mb: Some(8192),
+            cpu_millis: None,
+            disk_mb: Some(100_000),
+        };
+
+        assert!(host.shortfalls(Resources::BASE).is_empty());
+        assert_eq!(
+            host.summary().as_deref(),
+            Some("8.0 GB RAM, 97.7 GB free disk")
+        );
+        assert_eq!(HostCapacity::default().summary(), None);
+
+        let shortfalls = host.shortfalls(Resources::new(12_288, 16_000, 1000));
+        assert_eq!(
+            shortfalls,
+            vec!["~12.0 GB RAM needed, but this machine has 8.0 GB"]
+        );
+    }
+}
@@ -0,0 +1,582 @@
+use crate::{repos::Repository, resources::Resources};
+use serde::Deserialize;
+use std::{
+    io::{Error, ErrorKind},
+    path::PathBuf,
+};
+
+#[derive(Default, Clone, Eq, PartialEq, Debug)]
+pub struct OptionalService {
+    pub name: String,
+    pub label: String,
+    pub description: String,
+    pub compose_profile: Option<Vec<String>>,
+    pub compose_file: Option<PathBuf>,
+    pub repositories: Option<Vec<Repository>>,
+    pub resources: Resources,
+    pub urls: Vec<ServiceUrl>,
+    /// Environment variables read by `conf/lila.conf` when this service is enabled.
+    /// `{lila_url}` in a value is replaced with the URL of the development site.
+    pub lila_config: Vec<(String, String)>,
+}
+
+impl OptionalService {
+    fn new(name: &str, label: &str, description: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            label: label.to_string(),
+            description: description.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn with_profile(mut self, profile: &str) -> Self {
+        self.compose_profile = Some(vec![profile.to_string()]);
+        self
+    }
+
+    fn with_repository(mut self, repository: Repository) -> Self {
+        self.repositories = Some(vec![repository]);
+        self
+    }
+
+    fn with_url(mut self, url: ServiceUrl) -> Self {
+        self.urls.push(url);
+        self
+    }
+
+    fn with_lila_config(mut self, name: &str, value: &str) -> Self {
+        self.lila_config.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn with_resources(mut self, resources: Resources) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    pub fn has_profile(&self, profile: &str) -> bool {
+        self.compose_profile
+            .as_ref()
+            .is_some_and(|profiles| profiles.iter().any(|p| p == profile))
+    }
+
+    #[allow(clippy::too_many_lines)]
+    pub fn builtin() -> Vec<Self> {
+        vec![
+            Self::new(
+                "mongo-express",
+                "Database admin interface",
+                "Mongo Express for viewing database structure and data",
+            )
+            .with_profile("mongo-express")
+            .with_url(ServiceUrl::new("Mongodb manager", 8081, "/"))
+            .with_resources(Resources::new(128, 100, 300)),
+            Self::new(
+                "email",
+                "Outbound email testing",
+                "for capturing and debugging outbound email",
+            )
+            .with_profile("email")
+            .with_url(ServiceUrl::new("Email inbox", 8025, "/"))
+            .with_resources(Resources::new(64, 100, 50)),
+            Self::new(
+                "stockfish-play",
+                "Stockfish Play",
+                "for playing against the computer",
+            )
+            .with_profile("stockfish-play")
+            .with_repository(Repository::new("lichess-org", "lila-fishnet"))
+            .with_resources(Resources::new(2048, 2000, 2000)),
+            Self::new(
+                "stockfish-analysis",
+                "Stockfish Analysis",
+                "for requesting computer analysis of games",
+            )
+            .with_profile("stockfish-analysis")
+            .with_resources(Resources::new(512, 2000, 300)),
+            Self::new(
+                "external-engine",
+                "External Engine",
+                "for connecting a local chess engine to the analysis board",
+            )
+            .with_profile("external-engine")
+            .with_repository(Repository::new("lichess-org", "lila-engine"))
+            .with_resources(Resources::new(1024, 1000, 2500)),
+            Self::new(
+                "lifat",
+                "Larger static assets",
+                "Analysis board engines, background images, voice move models, etc",
+            )
+            .with_repository(Repository::new("lichess-org", "lifat"))
+            .with_resources(Resources::new(0, 0, 1500)),
+            Self::new("search", "Search", "for searching games, forum posts, etc")
+                .with_profile("search")
+                .with_repository(Repository::new("lichess-org", "lila-search"))
+                .with_url(ServiceUrl::new("Elasticsearch manager", 8092, "/"))
+                .with_url(ServiceUrl::new("lila-search docs", 9673, "/docs/"))
+                .with_resources(Resources::new(5120, 2000, 4000)),
+            Self::new(
+                "gifs",
+                "GIF + image generation",
+                "for generating animated GIFs and screenshots of games",
+            )
+            .with_profile("gifs")
+            .with_repository(Repository::new("lichess-org", "lila-gif"))
+            .with_url(ServiceUrl::new(
+                "lila-gif",
+                6175,
+                "/image.gif?fen=4k3/6KP/8/8/8/8/7p/8",
+            ))
+            .with_resources(Resources::new(1024, 1000, 2500)),
+            Self::new(
+                "thumbnails",
+                "Image uploads + thumbnails",
+                "for blog/coach/streamer images",
+            )
+            .with_profile("thumbnails")
+            .with_url(ServiceUrl::new("Picfit", 3001, "/healthcheck"))
+            .with_resources(Resources::new(128, 200, 300)),
+            Self::new("api-docs", "API docs", "standalone API documentation")
+                .with_profile("api-docs")
+                .with_repository(Repository::new("lichess-org", "api"))
+                .with_url(ServiceUrl::new("API docs", 8089, "/"))
+                .with_resources(Resources::new(512, 500, 800)),
+            Self::new("chessground", "Chessground", "standalone board UI")
+                .with_profile("chessground")
+                .with_repository(Repository::new("lichess-org", "chessground"))
+                .with_url(ServiceUrl::new("Chessground", 8090, "/demo.html"))
+                .with_resources(Resources::new(512, 500, 600)),
+            Self::new("pgn-viewer", "PGN Viewer", "standalone PGN viewer")
+                .with_profile("pgn-viewer")
+                .with_repository(Repository::new("lichess-org", "pgn-viewer"))
+                .with_url(ServiceUrl::new("PGN Viewer", 8091, "/"))
+                .with_resources(Resources::new(512, 500, 600)),
+            Self::new("scalachess", "Scalachess", "standalone chess logic library")
+                .with_repository(Repository::new("lichess-org", "scalachess"))
+                .with_resources(Resources::new(0, 0, 200)),
+            Self::new("mobile", "Mobile app", "Flutter-based mobile app")
+                .with_profile("mobile")
+                .with_repository(Repository::new("lichess-org", "mobile"))
+                .with_resources(Resources::new(4096, 2000, 12_000)),
+            Self::new(
+                "dartchess",
+                "Dartchess",
+                "standalone chess library for mobile platforms",
+            )
+            .with_repository(Repository::new("lichess-org", "dartchess"))
+            .with_resources(Resources::new(0, 0, 50)),
+            Self::new("berserk", "Berserk", "Python API client")
+                .with_repository(Repository::new("lichess-org", "berserk"))
+                .with_resources(Resources::new(0, 0, 50)),
+            Self::new(
+                "monitoring",
+                "Monitoring",
+                "Metric collection using InfluxDB",
+            )
+            .with_profile("monitoring")
+            .with_url(ServiceUrl::new("InfluxDB", 8086, "/").with_credentials("admin/password"))
+            .with_resources(Resources::new(512, 500, 500)),
+            Self::new("swiss-pairings", "Swiss Pairings", "bbpPairings tool")
+                .with_profile("swiss-pairings")
+                .with_repository(Repository::new("cyanfish", "bbpPairings"))
+                .with_resources(Resources::new(256, 500, 500)),
+            Self::new(
+                "push",
+                "Web push notifications",
+                "lila-push, for sending browser push notifications",
+            )
+            .with_profile("push")
+            .with_repository(Repository::new("lichess-org", "lila-push"))
+            .with_lila_config("PUSH_WEB_URL", "http://lila_push:9054")
+            .with_resources(Resources::new(1024, 500, 2000)),
+            Self::new(
+                "explorer",
+                "Opening explorer",
+                "lila-openingexplorer, indexing the games played on your site",
+            )
+            .with_profile("explorer")
+            .with_repository(Repository::new("lichess-org", "lila-openingexplorer"))
+            .with_lila_config("EXPLORER_ENDPOINT", "{lila_url}/_explorer")
+            .with_lila_config(
+                "EXPLORER_INTERNAL_ENDPOINT",
+                "http://lila_openingexplorer:9002",
+            )
+            .with_resources(Resources::new(1536, 1000, 3000)),
+            Self::new(
+                "tablebase",
+                "Tablebase",
+                "lila-tablebase, serving the Syzygy files placed in ./tablebases",
+            )
+            .with_profile("tablebase")
+            .with_repository(Repository::new("lichess-org", "lila-tablebase"))
+            .with_lila_config("EXPLORER_TABLEBASE_ENDPOINT", "{lila_url}/_tablebase")
+            .with_resources(Resources::new(1024, 1000, 2500)),
+            Self::new(
+                "lila-http",
+                "lila-http",
+                "HTTP server for high-traffic tournament pages",
+            )
+            .with_profile("lila-http")
+            .with_repository(Repository::new("lichess-org", "lila-http"))
+            .with_url(ServiceUrl::new("lila-http", 9165, "/"))
+            .with_resources(Resources::new(512, 500, 2000)),
+        ]
+    }
+
+    /// Built-in services followed by the ones registered in the local service catalog
+    pub fn all() -> std::io::Result<Vec<Self>> {
+        let mut services = Self::builtin();
+        services.extend(
+            Catalog::load()?
+                .services
+                .into_iter()
+                .map(CustomService::into_optional_service),
+        );
+        Ok(services)
+    }
+}
+
+/// A web interface exposed by a service on the host
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct ServiceUrl {
+    pub description: String,
+    pub port: u16,
+    #[serde(default = "ServiceUrl::default_path")]
+    pub path: String,
+    pub credentials: Option<String>,
+}
+
+impl ServiceUrl {
+    pub fn new(description: &str, port: u16, path: &str) -> Self {
+        Self {
+            description: description.to_string(),
+            port,
+            path: path.to_string(),
+            credentials: None,
+        }
+    }
+
+    fn with_credentials(mut self, credentials: &str) -> Self {
+        self.credentials = Some(credentials.to_string());
+        self
+    }
+
+    fn default_path() -> String {
+        "/".to_string()
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub services: Vec<String>,
+}
+
+impl Preset {
+    fn new(name: &str, description: &str, services: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            services: services.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    fn builtin() -> Vec<Self> {
+        vec![
+            Self::new(
+                "frontend",
+                "UI work on lila, chessground and the PGN viewer",
+                &["lifat", "chessground", "pgn-viewer"],
+            ),
+            Self::new(
+                "backend",
+                "Scala work on lila and scalachess",
+                &["mongo-express", "email", "scalachess"],
+            ),
+            Self::new(
+                "mobile",
+                "Flutter app development",
+                &["mobile", "dartchess"],
+            ),
+            Self::new(
+                "search",
+                "Elasticsearch and lila-search",
+                &["search", "mongo-express"],
+            ),
+            Self::new(
+                "engine",
+                "Playing against and analysing with Stockfish",
+                &[
+                    "stockfish-play",
+                    "stockfish-analysis",
+                    "external-engine",
+                    "lifat",
+                ],
+            ),
+            Self {
+                name: "everything".to_string(),
+                description: "Every optional service (needs a powerful machine)".to_string(),
+                services: OptionalService::builtin()
+                    .into_iter()
+                    .map(|service| service.name)
+                    .collect(),
+            },
+        ]
+    }
+
+    /// Built-in presets followed by the ones defined in the local service catalog.
+    /// A custom preset replaces a built-in one with the same name.
+    pub fn all() -> std::io::Result<Vec<Self>> {
+        let custom = Catalog::load()?.presets;
+
+        let mut presets: Vec<Self> = Self::builtin()
+            .into_iter()
+            .filter(|preset| !custom.iter().any(|c| c.name == preset.name))
+            .collect();
+        presets.extend(custom);
+
+        Ok(presets)
+    }
+
+    pub fn find(name: &str) -> std::io::Result<Self> {
+        let presets = Self::all()?;
+        let names: Vec<&str> = presets.iter().map(|preset| preset.name.as_str()).collect();
+
+        presets
+            .iter()
+            .find(|preset| preset.name == name)
+            .cloned()
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!(
+                        "Unknown preset `{name}`. Available presets: {}",
+                        names.join(", ")
+                    ),
+                )
+            })
+    }
+
+    pub fn optional_services(&self) -> std::io::Result<Vec<OptionalService>> {
+        let all = OptionalService::all()?;
+
+        self.services
+            .iter()
+            .map(|name| {
+                all.iter()
+                    .find(|service| &service.name == name)
+                    .cloned()
+                    .ok_or_else(|| {
+                        Error::new(
+                            ErrorKind::InvalidData,
+                            format!("Preset `{}` refers to unknown service `{name}`", self.name),
+                        )
+                    })
+            })
+            .collect()
+    }
+}
+
+/// A service that is not part of lila-docker itself, registered in `services.toml`
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct CustomService {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub repository: Option<String>,
+    pub clone_path: Option<PathBuf>,
+    pub compose_file: Option<PathBuf>,
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub urls: Vec<ServiceUrl>,
+}
+
+impl CustomService {
+    fn into_optional_service(self) -> OptionalService {
+        OptionalService {
+            label: self.name.clone(),
+            repositories: self
+                .repository
+                .map(|url| vec![Repository::from_url(&url, self.clone_path)]),
+            compose_profile: self.profile.map(|profile| vec![profile]),
+            compose_file: self.compose_file,
+            name: self.name,
+            description: self.description,
+            resources: Resources::default(),
+            urls: self.urls,
+            lila_config: vec![],
+        }
+    }
+}
+
+/// Local additions to the service catalog, read from `services.toml`
+#[derive(Debug, Default, Deserialize)]
+pub struct Catalog {
+    #[serde(default)]
+    pub presets: Vec<Preset>,
+    #[serde(default)]
+    pub services: Vec<CustomService>,
+}
+
+impl Catalog {
+    const CATALOG_TOML: &'static str = "services.toml";
+
+    pub fn load() -> std::io::Result<Self> {
+        match std::fs::read_to_string(Self::CATALOG_TOML) {
+            Ok(contents) => Self::parse(&contents),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn parse(contents: &str) -> std::io::Result<Self> {
+        toml::from_str(contents).map_err(|err| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Failed to parse {}: {err}", Self::CATALOG_TOML),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_names_are_unique() {
+        let services = OptionalService::builtin();
+        let mut names: Vec<&str> = services
+            .iter()
+            .map(|service| service.name.as_str())
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), services.len());
+    }
+
+    #[test]
+    fn test_builtin_presets_resolve() {
+        for preset in Preset::builtin() {
+            assert!(preset.optional_services().is_ok(), "{}", preset.name);
+        }
+
+        let everything = Preset::builtin().pop().unwrap();
+        assert_eq!(
+            everything.optional_services().unwrap(),
+            OptionalService::builtin()
+        );
+    }
+
+    #[test]
+    fn test_documented_presets_exist() {
+        let readme = include_str!("../../README.md");
+        let documented = readme
+            .lines()
+            .find(|line| line.starts_with("Instead of picking optional services"))
+            .unwrap();
+        let mut names: Vec<&str> = documented.split('`').skip(1).step_by(2).collect();
+        names.extend(
+            readme
+                .split("PRESET=")
+                .skip(1)
+                .filter_map(|rest| rest.split(['/', ',', '&']).next()),
+        );
+
+        let builtin = Preset::builtin();
+        for name in names {
+            assert!(
+                builtin.iter().any(|preset| preset.name == name),
+                "{name} is not a built-in preset"
+            );
+        }
+    }
+
+    #[test]
+    fn test_custom_preset_from_catalog() {
+        let catalog = Catalog::parse(
+            r#"
+            [[presets]]
+            name = "puzzles"
+            services = ["mongo-express", "scalachess"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            catalog.presets,
+            vec![Preset::new("puzzles", "", &["mongo-express", "scalachess"])]
+        );
+
+        let services = catalog.presets[0].optional_services().unwrap();
+        assert_eq!(
+            services.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(),
+            vec!["mongo-express", "scalachess"]
+        );
+    }
+
+    #[test]
+    fn test_preset_with_unknown_service() {
+        let preset = Preset::new("broken", "", &["mongo-express", "nope"]);
+        assert_eq!(
+            preset.optional_services().unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_custom_service_from_catalog() {
+        let catalog = Catalog::parse(
+            r#"
+            [[services]]
+            name = "lila-helper"
+            description = "Internal helper service"
+            repository = "https://github.com/my-team/lila-helper.git"
+            compose_file = "compose/lila-helper.yml"
+            profile = "lila-helper"
+
+            [[services.urls]]
+            description = "Helper dashboard"
+            port = 9100
+
+            [[services]]
+            name = "notes"
+            repository = "git@github.com:my-team/notes.git"
+            clone_path = "repos/team-notes"
+            "#,
+        )
+        .unwrap();
+
+        let services: Vec<OptionalService> = catalog
+            .services
+            .into_iter()
+            .map(CustomService::into_optional_service)
+            .collect();
+
+        assert_eq!(services[0].name, "lila-helper");
+        assert_eq!(services[0].label, "lila-helper");
+        assert_eq!(
+            services[0].compose_file,
+            Some(PathBuf::from("compose/lila-helper.yml"))
+        );
+        assert!(services[0].has_profile("lila-helper"));
+        assert_eq!(
+            services[0].urls,
+            vec![ServiceUrl::new("Helper dashboard", 9100, "/")]
+        );
+
+        let helper = &services[0].repositories.as_ref().unwrap()[0];
+        assert_eq!(helper.full_name(), "my-team/lila-helper");
+        assert_eq!(helper.url(), "https://github.com/my-team/lila-helper.git");
+        assert_eq!(helper.clone_path(), PathBuf::from("repos/lila-helper"));
+
+        assert_eq!(services[1].compose_profile, None);
+        let notes = &services[1].repositories.as_ref().unwrap()[0];
+        assert_eq!(notes.full_name(), "my-team/notes");
+        assert_eq!(notes.url(), "git@github.com:my-team/notes.git");
+        assert_eq!(notes.clone_path(), PathBuf::from("repos/team-notes"));
+    }
+}